@@ -0,0 +1,106 @@
+// Keys the backend signs license proofs and revocation lists with. Every key has an id and an
+// optional validity window. A license proof is checked with the keys whose window contains the
+// delegation timestamp, so after a rotation the proofs issued with the old key keep verifying
+// for the delegations made while it was in use. Revocation lists are checked with the keys
+// valid at their signed `issued_at` time.
+//
+// The delegation timestamp is chosen by the license owner and is not covered by the license proof,
+// so `valid_until` doesn't revoke a compromised key: its proofs still verify with a timestamp
+// inside the window. Such a key must be removed from the keyring.
+
+use crate::config::BackendKey;
+use crate::decode_backend_key;
+use crate::errors::IgniterError;
+use crate::network::NetworkProfile;
+
+// The default keyring is empty and verifies nothing
+#[derive(Debug, Clone, Default)]
+pub struct BackendKeyring {
+    keys: Vec<(BackendKey, [u8; 32])>,
+}
+
+impl BackendKey {
+    pub fn new(key_id: &str, pubkey: &str) -> Self {
+        BackendKey {
+            key_id: key_id.to_string(),
+            pubkey: pubkey.to_string(),
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    // `valid_until` is exclusive
+    pub fn is_valid_at(&self, timestamp: u64) -> bool {
+        self.valid_from.is_none_or(|from| timestamp >= from)
+            && self.valid_until.is_none_or(|until| timestamp < until)
+    }
+}
+
+impl BackendKeyring {
+    pub fn new(keys: Vec<BackendKey>) -> Result<Self, IgniterError> {
+        if keys.is_empty() {
+            return Err(IgniterError::InvalidBackendKey);
+        }
+        let keys = keys
+            .into_iter()
+            .map(|key| decode_backend_key(&key.pubkey).map(|bytes| (key, bytes)))
+            .collect::<Result<_, _>>()?;
+        Ok(BackendKeyring { keys })
+    }
+
+    // `backend_keys` from config.yaml replace the keys of the network, e.g. in private test
+    // networks
+    pub fn from_config(
+        network: &NetworkProfile,
+        backend_keys: &[BackendKey],
+    ) -> Result<Self, IgniterError> {
+        if !backend_keys.is_empty() {
+            return Self::new(backend_keys.to_vec());
+        }
+        if network.backend_keys.is_empty() {
+            return Err(IgniterError::NoNetworkBackendKeys(network.name.clone()));
+        }
+        Self::new(network.backend_keys.clone())
+    }
+
+    // Ids and public keys of the keys valid at `timestamp`, in keyring order
+    pub fn keys_at(&self, timestamp: u64) -> impl Iterator<Item = (&str, &[u8; 32])> {
+        self.keys
+            .iter()
+            .filter(move |(key, _)| key.is_valid_at(timestamp))
+            .map(|(key, bytes)| (key.key_id.as_str(), bytes))
+    }
+
+    pub fn contains(&self, pubkey: &str) -> bool {
+        self.keys.iter().any(|(key, _)| key.pubkey.eq_ignore_ascii_case(pubkey))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_selected_by_their_window() {
+        let old_pk = "ee99af158c8b50f6bee3360615e08db957bd898568638f308d8f320cf4e37638";
+        let new_pk = "75631f108a226740a8649ff7946bf19d2884c373615d27f5b6d1863b5d97adf3";
+        let keyring = BackendKeyring::new(vec![
+            BackendKey { valid_until: Some(2000), ..BackendKey::new("old", old_pk) },
+            BackendKey { valid_from: Some(1500), ..BackendKey::new("new", new_pk) },
+        ])
+        .unwrap();
+        let ids = |timestamp| keyring.keys_at(timestamp).map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids(1000), ["old"]);
+        assert_eq!(ids(1500), ["old", "new"]);
+        assert_eq!(ids(2000), ["new"]);
+        assert!(keyring.contains(&new_pk.to_uppercase()));
+
+        assert!(BackendKeyring::new(vec![]).is_err());
+        assert!(BackendKeyring::new(vec![BackendKey::new("bad", "abc")]).is_err());
+
+        let local = NetworkProfile::local();
+        assert!(BackendKeyring::from_config(&local, &[]).is_err());
+        let keyring = BackendKeyring::from_config(&local, &[BackendKey::new("new", new_pk)]);
+        assert_eq!(keyring.unwrap().keys_at(0).count(), 1);
+    }
+}
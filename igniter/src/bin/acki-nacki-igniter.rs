@@ -3,15 +3,25 @@ use std::process::exit;
 use std::thread;
 
 use acki_nacki_igniter::cli::CLI;
-use acki_nacki_igniter::IGNITER_IMAGE;
+use acki_nacki_igniter::cli::CLI_ARGS;
+use acki_nacki_igniter::config_reloader::ConfigReloader;
+use acki_nacki_igniter::revoked_license_watcher::DroppedLicenses;
+use acki_nacki_igniter::utils::init_tracing;
 use tracing::error;
 use tracing::info;
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
+use tracing::warn;
 use updater::ContainerUpdater;
 use updater::DEFAULT_UPDATE_INTERVAL;
 
 fn main() {
+    if let Some(command) = &CLI_ARGS.command {
+        if let Err(err) = acki_nacki_igniter::commands::run(command) {
+            eprintln!("Error: {err:#}");
+            exit(1);
+        }
+        exit(0);
+    }
+
     _ = *CLI; // make sure we have the value or panic before we start
 
     eprintln!("Starting server: advertise address {}", CLI.config.advertise_addr);
@@ -30,14 +40,7 @@ fn main() {
 
 #[tokio::main]
 pub async fn tokio_main() {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::fmt::layer() //
-                .with_file(true)
-                .with_line_number(true),
-        )
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    init_tracing();
 
     if let Err(err) = tokio_main_inner().await {
         error!(error=?err, "tokio_main failed");
@@ -54,10 +57,11 @@ async fn tokio_main_inner() -> anyhow::Result<()> {
     let params = CLI.clone();
 
     let updater_handle = tokio::spawn(async move {
-        if params.config.auto_update {
+        let image = params.network.image.clone().filter(|_| params.config.auto_update);
+        if let Some(image) = image {
             info!("Auto update enabled");
             let updater = ContainerUpdater::try_new(
-                IGNITER_IMAGE.to_owned(),
+                image,
                 DEFAULT_UPDATE_INTERVAL,
                 CLI.docker_socket.clone(),
                 CLI.docker_config.clone(),
@@ -66,34 +70,32 @@ async fn tokio_main_inner() -> anyhow::Result<()> {
 
             updater.run().await
         } else {
-            info!("Auto update disabled");
+            if params.config.auto_update {
+                warn!("Auto update disabled: network {} has no image", params.network.name);
+            } else {
+                info!("Auto update disabled");
+            }
             std::future::pending().await
         }
     });
 
-    let initial_key_values = params.to_gossip()?;
+    tracing::info!("Gossip advertise addr: {:?}", CLI.config.advertise_addr);
 
-    let listen_addr = CLI.config.listen_addr;
-    let api_addr = CLI.config.api_addr;
-    let seeds = CLI.config.seeds.clone();
-    let advertise_addr = CLI.config.advertise_addr;
-    let cluster_id = CLI.config.cluster_id.clone();
+    let (chitchat, gossip_handle, gossip_rest_handle, verified) =
+        acki_nacki_igniter::gossip::run(&CLI, chitchat::transport::UdpTransport).await?;
 
-    tracing::info!("Gossip advertise addr: {:?}", advertise_addr);
-
-    let (chitchat, gossip_handle, gossip_rest_handle) = acki_nacki_igniter::gossip::run(
-        listen_addr,
-        api_addr,
-        chitchat::transport::UdpTransport,
-        advertise_addr,
-        seeds,
-        cluster_id,
-        initial_key_values,
+    let dropped = DroppedLicenses::default();
+    let revoked_licenses_watcher = acki_nacki_igniter::revoked_license_watcher::run(
+        verified,
+        chitchat.clone(),
+        &CLI,
+        dropped.clone(),
     )
-    .await?;
+    .await;
 
-    let revoked_licenses_watcher =
-        acki_nacki_igniter::revoked_license_watcher::run(chitchat, params.keys.wallet.pubkey).await;
+    let config_path = CLI_ARGS.config.clone().expect("config path is checked by CLI");
+    let config_reloader =
+        ConfigReloader::new(chitchat, CLI.clone(), config_path, dropped)?.run()?;
 
     tokio::select! {
         v = updater_handle => {
@@ -109,5 +111,8 @@ async fn tokio_main_inner() -> anyhow::Result<()> {
          v = revoked_licenses_watcher => {
             anyhow::bail!("License watcher failed: {v:?}");
         }
+        v = config_reloader => {
+            anyhow::bail!("Config reloader failed: {v:?}");
+        }
     }
 }
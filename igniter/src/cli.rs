@@ -1,62 +1,85 @@
+use std::net::SocketAddr;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::LazyLock;
 
-use anyhow::bail;
+use anyhow::anyhow;
+use clap::Args;
 use clap::Parser;
-use reqwest;
-use reqwest::blocking::Client;
+use clap::Subcommand;
 use serde::Serialize;
-use serde_yaml;
 
 use crate::config::read_yaml;
 use crate::config::Config;
 use crate::config::Keys;
-use crate::config::DEV_MODE;
-use crate::config::IGNITER_SEEDS;
+use crate::keys_source::KeysSource;
+use crate::network::NetworkProfile;
+use crate::seeds::SeedSources;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Params {
     pub config: Config,
+    pub network: NetworkProfile,
     pub docker_socket: Option<String>,
     pub docker_config: Option<String>,
     pub keys: Keys,
 }
 
+/// Parsed command line, shared by the server and the subcommands
+pub static CLI_ARGS: LazyLock<CliArgs> = LazyLock::new(CliArgs::parse);
+
+impl Params {
+    // Reads config and keys, selects the network and resolves the seeds list
+    pub fn load(
+        config_path: &Path,
+        keys: &KeysSource,
+        network: &NetworkArgs,
+        docker_socket: Option<String>,
+        docker_config: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let mut config = read_yaml::<Config>(config_path)
+            .map_err(|error| anyhow!("Error parsing config file {config_path:?}: {error:?}"))?;
+
+        let network = NetworkProfile::select(network, Some(&config))?;
+        eprintln!("Using network {}", network.name);
+
+        config.seeds = SeedSources::from_config(&config, &network)
+            .and_then(|sources| sources.load())
+            .map_err(|error| anyhow!("Initialization error: unable to get seeds: {error}"))?;
+
+        let keys = keys.load().map_err(|error| anyhow!("Error loading keys: {error:?}"))?;
+
+        Ok(Params { config, network, docker_socket, docker_config, keys })
+    }
+}
+
 /// Cli args are globaly accessible for convenience
 pub static CLI: LazyLock<Params> = LazyLock::new(|| {
-    let cli: CliArgs = CliArgs::parse();
-    let mut config = match read_yaml::<Config>(&cli.config) {
-        Ok(config) => config,
-        Err(error) => {
-            eprintln!("Error parsing config file {:?}: {:?}", cli.config, error);
-            std::process::exit(1);
-        }
+    let cli = &*CLI_ARGS;
+    // `subcommand_negates_reqs` makes clap enforce the config path when no subcommand is given
+    let Some(config_path) = &cli.config else {
+        eprintln!("--config is required to run the server");
+        std::process::exit(1);
     };
-
-    config.seeds = match read_seeds(&IGNITER_SEEDS) {
-        Ok(seeds) => {
-            seeds
-            // vec!["127.0.0.1:10000".to_string(), "127.0.0.1:10001".to_string()]
-        }
-        Err(error) => {
-            eprintln!(
-                "Initialization error: unable to download seeds from {} {error}",
-                *IGNITER_SEEDS
-            );
-            std::process::exit(1);
-        }
+    let keys = KeysSource {
+        file: cli.keys.clone(),
+        fd: cli.keys_fd,
+        secrets_dir: cli.secrets_dir.clone(),
+        env: true,
     };
-
-    //
-
-    let keys = match read_yaml::<Keys>(&cli.keys) {
-        Ok(keys) => keys,
+    match Params::load(
+        config_path,
+        &keys,
+        &cli.network,
+        cli.docker_socket.clone(),
+        cli.docker_config.clone(),
+    ) {
+        Ok(params) => params,
         Err(error) => {
-            eprintln!("Error parsing keys file {:?}: {:?}", cli.keys, error);
+            eprintln!("{error}");
             std::process::exit(1);
         }
-    };
-    Params { config, docker_socket: cli.docker_socket, docker_config: cli.docker_config, keys }
+    }
 });
 
 pub static LONG_VERSION: LazyLock<String> = LazyLock::new(|| {
@@ -78,12 +101,25 @@ BUILD_TIME={}",
 /// Acki Nacki Gossip Igniter
 #[derive(Parser, Debug, Clone, Serialize)]
 #[command(author, long_version = &**LONG_VERSION, about, long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 pub struct CliArgs {
+    /// Keys file, see `keys_source` for the other sources and their precedence
     #[arg(short, long)]
-    pub keys: PathBuf,
+    pub keys: Option<PathBuf>,
 
-    #[arg(short, long)]
-    pub config: PathBuf,
+    /// Inherited file descriptor to read the keys document from, takes precedence over --keys
+    #[arg(long, env = "IGNITER_KEYS_FD")]
+    pub keys_fd: Option<i32>,
+
+    /// Directory with secret files named after the keys fields, e.g. `wallet_secret`
+    #[arg(long, env = "CREDENTIALS_DIRECTORY")]
+    pub secrets_dir: Option<PathBuf>,
+
+    #[arg(short, long, required = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
 
     /// host's docker UNIX socket
     #[arg(long, env, default_value = "/var/run/docker.sock")]
@@ -92,17 +128,182 @@ pub struct CliArgs {
     /// host's docker config
     #[arg(long, env)]
     pub docker_config: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
-fn read_seeds(url: &str) -> anyhow::Result<Vec<String>> {
-    let client = Client::new();
-    let mut request = client.get(url);
-    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        request = request.bearer_auth(token);
-    } else if *DEV_MODE {
-        bail!("GITHUB_TOKEN required")
-    }
-    let body = request.send()?.text()?;
-    let seeds: Vec<String> = serde_yaml::from_str(&body)?;
-    Ok(seeds)
+#[derive(Args, Debug, Clone, Default, Serialize)]
+pub struct NetworkArgs {
+    /// Network profile: mainnet, devnet, local or one from the networks file. Overrides
+    /// `network` of config.yaml, mainnet by default
+    #[arg(long, global = true, env = "IGNITER_NETWORK")]
+    pub network: Option<String>,
+
+    /// YAML file with custom network profiles. Overrides `networks_file` of config.yaml
+    #[arg(long, global = true, env = "IGNITER_NETWORKS_FILE")]
+    pub networks_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone, Serialize)]
+pub enum Command {
+    /// Generate node owner (wallet) and BLS key pairs and write them to a new keys file
+    Keygen(KeygenArgs),
+
+    /// Sign a license delegation to a node provider (run by the license owner)
+    SignDelegation(SignDelegationArgs),
+
+    /// Confirm a delegated license for a BK node and print its `signatures` entry (run by the node provider)
+    ConfirmDelegation(ConfirmDelegationArgs),
+
+    /// Check all signatures of a single `signatures` entry
+    VerifySignature(VerifySignatureArgs),
+
+    /// Validate config and keys files offline and print a report for every license
+    CheckConfig(CheckConfigArgs),
+
+    /// Write a detached signature of a seeds list next to it
+    SignSeeds(SignSeedsArgs),
+
+    /// Write a detached signature of a license revocation list next to it (run by the backend)
+    SignRevocations(SignRevocationsArgs),
+
+    /// Run one igniter instance for every subdirectory with config.yaml and keys.yaml
+    Supervise(SuperviseArgs),
+
+    /// Encrypt a keys file with a passphrase
+    EncryptKeys(ConvertKeysArgs),
+
+    /// Decrypt a keys file encrypted with `encrypt-keys`
+    DecryptKeys(ConvertKeysArgs),
+
+    /// Request `/health` of the running server at the `api_addr` of its config, used by the image
+    Healthcheck(HealthcheckArgs),
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct KeygenArgs {
+    /// Path of the keys file to create, an existing file is never overwritten
+    #[arg(short, long, default_value = "keys.yaml")]
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct SignDelegationArgs {
+    #[arg(long)]
+    pub license_id: String,
+
+    /// License owner key pair file (`tvm-cli getkeypair` format)
+    #[arg(long)]
+    pub owner_keys: PathBuf,
+
+    #[arg(long)]
+    pub provider_pubkey: String,
+
+    /// Delegation timestamp in seconds, defaults to the current time
+    #[arg(long)]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct ConfirmDelegationArgs {
+    #[arg(long)]
+    pub license_id: String,
+
+    #[arg(long)]
+    pub license_owner_pubkey: String,
+
+    /// Node provider key pair file (`tvm-cli getkeypair` format)
+    #[arg(long)]
+    pub provider_keys: PathBuf,
+
+    /// keys.yaml of the BK node the license is attached to
+    #[arg(long)]
+    pub node_keys: PathBuf,
+
+    /// Signature received from the license owner
+    #[arg(long)]
+    pub delegation_sig: String,
+
+    /// Timestamp the license owner used for `delegation_sig`
+    #[arg(long)]
+    pub timestamp: u64,
+
+    /// License proof received from the Acki Nacki backend
+    #[arg(long)]
+    pub license_proof_sig: String,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct CheckConfigArgs {
+    #[arg(short, long)]
+    pub keys: PathBuf,
+
+    #[arg(short, long)]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct VerifySignatureArgs {
+    /// File with a `signatures` section, e.g. config.yaml
+    #[arg(long)]
+    pub input: PathBuf,
+
+    /// Entry to check, may be omitted if the file contains only one entry
+    #[arg(long)]
+    pub license_id: Option<String>,
+
+    /// keys.yaml of the BK node the license is attached to
+    #[arg(long)]
+    pub node_keys: PathBuf,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct SignSeedsArgs {
+    /// Seeds list to sign, the signature is written to `<input>.sig`
+    #[arg(long)]
+    pub input: PathBuf,
+
+    /// Key pair file of the seeds authority (tvm-cli format)
+    #[arg(long)]
+    pub authority_keys: PathBuf,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct SignRevocationsArgs {
+    /// Revocation list to sign, the signature is written to `<input>.sig`
+    #[arg(long)]
+    pub input: PathBuf,
+
+    /// Key pair file of the backend (tvm-cli format)
+    #[arg(long)]
+    pub backend_keys: PathBuf,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct SuperviseArgs {
+    /// Directory with an instance subdirectory per BK node
+    #[arg(long)]
+    pub dir: PathBuf,
+
+    /// Address of the combined status API of all instances
+    #[arg(long, default_value = "0.0.0.0:10100")]
+    pub status_addr: SocketAddr,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct HealthcheckArgs {
+    /// config.yaml of the server
+    #[arg(short, long, env = "IGNITER_CONFIG", default_value = "/config.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub struct ConvertKeysArgs {
+    #[arg(long)]
+    pub input: PathBuf,
+
+    /// Path of the file to create, an existing file is never overwritten
+    #[arg(long)]
+    pub output: PathBuf,
 }
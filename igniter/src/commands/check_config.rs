@@ -0,0 +1,144 @@
+use anyhow::anyhow;
+use anyhow::bail;
+
+use crate::backend_keys::BackendKeyring;
+use crate::check_license_count;
+use crate::cli::CheckConfigArgs;
+use crate::cli::NetworkArgs;
+use crate::config::read_yaml;
+use crate::config::Config;
+use crate::config::InvalidLicensesPolicy;
+use crate::encrypted_keys::read_keys;
+use crate::errors::IgniterError;
+use crate::network::NetworkProfile;
+use crate::open_api::routes::check_proxy_socket_addresses;
+use crate::LicenseCheck;
+use crate::LicenseReport;
+use crate::VerifiedSignatures;
+
+pub fn run(args: &CheckConfigArgs, network: &NetworkArgs) -> anyhow::Result<()> {
+    let config = read_yaml::<Config>(&args.config)
+        .map_err(|err| anyhow!("Can't parse config file {:?}: {err}", args.config))?;
+    let keys = read_keys(&args.keys)
+        .map_err(|err| anyhow!("Can't parse keys file {:?}: {err}", args.keys))?;
+
+    let network = NetworkProfile::select(network, Some(&config))?;
+    println!("{:<16} {}", "network", network.name);
+
+    let mut problems = 0;
+    let mut print_check = |name: &str, result: Result<(), IgniterError>| {
+        match result {
+            Ok(()) => println!("{name:<16} ok"),
+            Err(err) => {
+                problems += 1;
+                println!("{name:<16} FAIL: {err}");
+            }
+        };
+    };
+    print_check("proxies", check_proxies(&config));
+    print_check("license count", check_license_count(config.signatures.len()));
+
+    let backend_keys = BackendKeyring::from_config(&network, &config.backend_keys)?;
+    let reports = VerifiedSignatures::validate(
+        &config.signatures,
+        &backend_keys,
+        &keys.wallet.pubkey,
+        &keys.bls.pubkey,
+        &network.timestamp_policy.strictest(&config.timestamp_policy),
+    )?;
+    if !reports.is_empty() {
+        println!();
+        print_table(&reports);
+    }
+    let invalid = reports.iter().filter(|report| !report.is_ok()).count();
+    if invalid > 0 && config.invalid_licenses == InvalidLicensesPolicy::Skip {
+        println!(
+            "\nWith `invalid_licenses: skip` the node will start with {} valid license(s)",
+            reports.len() - invalid
+        );
+    }
+    problems += invalid;
+
+    if problems > 0 {
+        bail!("{problems} problem(s) found in {:?}", args.config);
+    }
+    println!("\nConfig {:?} is valid", args.config);
+    Ok(())
+}
+
+fn check_proxies(config: &Config) -> Result<(), IgniterError> {
+    let proxies = serde_json::to_string(&config.proxies)?;
+    if !check_proxy_socket_addresses(Some(&proxies)) {
+        return Err(IgniterError::InvalidProxies);
+    }
+    Ok(())
+}
+
+fn print_table(reports: &[LicenseReport]) {
+    let id_width = reports.iter().map(|report| report.license_id.len()).max().unwrap_or(0);
+    let names = LicenseCheck::ALL.map(|check| check.to_string());
+    println!("{:<id_width$}  {}  backend_key", "license_id", names.join("  "));
+    for report in reports {
+        let cells = names.iter().zip(&report.checks).map(|(name, check)| {
+            let cell = if check.is_ok() { "ok" } else { "FAIL" };
+            format!("{cell:<width$}", width = name.len())
+        });
+        let row = format!(
+            "{:<id_width$}  {}  {}",
+            report.license_id,
+            cells.collect::<Vec<_>>().join("  "),
+            report.backend_key_id.as_deref().unwrap_or("")
+        );
+        println!("{}", row.trim_end());
+    }
+    for report in reports.iter().filter(|report| !report.is_ok()) {
+        println!();
+        println!("{}:", report.license_id);
+        for (check, err) in report.failures() {
+            println!("  {check}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Keys;
+
+    #[test]
+    fn proxies_are_checked() {
+        let mut config = read_yaml::<Config>("./tests/config.yaml").expect("File exists and valid");
+        assert!(check_proxies(&config).is_ok());
+
+        config.proxies[0].socket_address = None;
+        assert!(matches!(check_proxies(&config), Err(IgniterError::InvalidProxies)));
+    }
+
+    #[test]
+    fn every_license_check_is_reported() {
+        let keys = read_yaml::<Keys>("./tests/keys.yaml").expect("File exists and valid");
+        let mut config = read_yaml::<Config>("./tests/config.yaml").expect("File exists and valid");
+        config.signatures.push(config.signatures[0].clone());
+        let backend_keys = BackendKeyring::from_config(&NetworkProfile::devnet(), &[]).unwrap();
+
+        let reports = VerifiedSignatures::validate(
+            &config.signatures,
+            &backend_keys,
+            &keys.wallet.pubkey,
+            &keys.bls.pubkey,
+            &config.timestamp_policy,
+        )
+        .unwrap();
+        assert_eq!(reports.len(), 2);
+        // Test signatures are not made for the test keys, so all signature checks fail
+        assert!(reports[0].checks[..3].iter().all(Result::is_err));
+        assert!(reports[0].checks[3..].iter().all(Result::is_ok));
+        assert!(matches!(reports[1].checks[4], Err(IgniterError::DuplicateLicenseId(_))));
+    }
+
+    #[test]
+    fn license_count_is_checked() {
+        assert!(matches!(check_license_count(0), Err(IgniterError::NoLicenses)));
+        assert!(check_license_count(1).is_ok());
+    }
+}
@@ -0,0 +1,54 @@
+// Docker HEALTHCHECK of the image. The API address is taken from config.yaml, so the check follows
+// a changed `api_addr`.
+
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use reqwest::blocking::Client;
+
+use crate::cli::HealthcheckArgs;
+use crate::config::read_yaml;
+use crate::config::Config;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn run(args: &HealthcheckArgs) -> anyhow::Result<()> {
+    let config = read_yaml::<Config>(&args.config)
+        .map_err(|err| anyhow!("Can't parse config file {:?}: {err}", args.config))?;
+    let url = format!("http://{}/health", local_addr(config.api_addr));
+    let response = Client::builder().timeout(TIMEOUT).build()?.get(&url).send()?;
+    let status = response.status();
+    let body = response.text().unwrap_or_default();
+    if !status.is_success() {
+        bail!("{url} responded with {status}: {body}");
+    }
+    println!("{body}");
+    Ok(())
+}
+
+// An API listening on all interfaces is reached over the loopback
+fn local_addr(api_addr: SocketAddr) -> SocketAddr {
+    match api_addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, api_addr.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, api_addr.port()).into(),
+        _ => api_addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_api_addr_is_reached_over_loopback() {
+        let local = |addr: &str| local_addr(addr.parse().unwrap()).to_string();
+        assert_eq!(local("0.0.0.0:10001"), "127.0.0.1:10001");
+        assert_eq!(local("[::]:10002"), "[::1]:10002");
+        assert_eq!(local("10.0.0.1:10003"), "10.0.0.1:10003");
+    }
+}
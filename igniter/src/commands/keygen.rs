@@ -0,0 +1,13 @@
+use crate::cli::KeygenArgs;
+use crate::config::write_yaml_new;
+use crate::config::Keys;
+
+pub fn run(args: &KeygenArgs) -> anyhow::Result<()> {
+    let keys = Keys::generate()?;
+    write_yaml_new(&args.output, &keys)?;
+
+    println!("Keys saved to {:?}", args.output);
+    println!("Node owner pubkey: {}", keys.wallet.pubkey);
+    println!("BLS pubkey: {}", keys.bls.pubkey);
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use crate::cli::ConvertKeysArgs;
+use crate::config::read_yaml;
+use crate::config::write_yaml_new;
+use crate::config::Keys;
+use crate::encrypted_keys::passphrase;
+use crate::encrypted_keys::read_keys;
+use crate::encrypted_keys::EncryptedKeys;
+use crate::encrypted_keys::EncryptedKeysFile;
+use crate::encrypted_keys::KdfParams;
+
+pub fn encrypt(args: &ConvertKeysArgs) -> anyhow::Result<()> {
+    let keys = read_yaml::<Keys>(&args.input)?;
+    let passphrase = passphrase("New passphrase: ", true)?;
+    let encrypted_keys = EncryptedKeys::encrypt(&keys, &passphrase, KdfParams::default())?;
+    write_yaml_new(&args.output, &EncryptedKeysFile { encrypted_keys })?;
+    println!("Encrypted keys saved to {:?}", args.output);
+    Ok(())
+}
+
+pub fn decrypt(args: &ConvertKeysArgs) -> anyhow::Result<()> {
+    let keys = read_keys(&args.input)?;
+    write_yaml_new(&args.output, &keys)?;
+    println!("Decrypted keys saved to {:?}", args.output);
+    Ok(())
+}
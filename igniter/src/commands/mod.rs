@@ -0,0 +1,31 @@
+// Subcommands of the igniter binary. All of them except `supervise` and `healthcheck` work offline.
+pub mod check_config;
+pub mod healthcheck;
+pub mod keygen;
+pub mod keys;
+pub mod signatures;
+
+use crate::cli::Command;
+use crate::cli::CLI_ARGS;
+use crate::supervisor;
+
+pub fn run(command: &Command) -> anyhow::Result<()> {
+    match command {
+        Command::Keygen(args) => keygen::run(args),
+        Command::SignDelegation(args) => signatures::sign_delegation(args),
+        Command::ConfirmDelegation(args) => signatures::confirm_delegation(args, &CLI_ARGS.network),
+        Command::VerifySignature(args) => signatures::verify_signature(args, &CLI_ARGS.network),
+        Command::CheckConfig(args) => check_config::run(args, &CLI_ARGS.network),
+        Command::SignSeeds(args) => signatures::sign_seeds(args),
+        Command::SignRevocations(args) => signatures::sign_revocations(args, &CLI_ARGS.network),
+        Command::EncryptKeys(args) => keys::encrypt(args),
+        Command::DecryptKeys(args) => keys::decrypt(args),
+        Command::Healthcheck(args) => healthcheck::run(args),
+        Command::Supervise(args) => supervisor::run(
+            args,
+            &CLI_ARGS.network,
+            CLI_ARGS.docker_socket.clone(),
+            CLI_ARGS.docker_config.clone(),
+        ),
+    }
+}
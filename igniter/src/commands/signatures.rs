@@ -0,0 +1,261 @@
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use ed25519_dalek::Signer;
+use ed25519_dalek::SigningKey;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::backend_keys::BackendKeyring;
+use crate::cli::ConfirmDelegationArgs;
+use crate::cli::NetworkArgs;
+use crate::cli::SignDelegationArgs;
+use crate::cli::SignRevocationsArgs;
+use crate::cli::SignSeedsArgs;
+use crate::cli::VerifySignatureArgs;
+use crate::config::read_yaml;
+use crate::config::KeyPairFile;
+use crate::config::Keys;
+use crate::config::LicenceSignature;
+use crate::config::SignaturesSection;
+use crate::encrypted_keys::read_keys;
+use crate::network::NetworkProfile;
+use crate::revocations::RevocationList;
+use crate::seeds::signature_path;
+
+// Part of the `signatures` entry the license owner passes to the node provider
+#[derive(Debug, Serialize, Deserialize)]
+struct Delegation {
+    license_id: String,
+    license_owner_pubkey: String,
+    provider_pubkey: String,
+    delegation_sig: String,
+    timestamp: u64,
+}
+
+pub fn sign_delegation(args: &SignDelegationArgs) -> anyhow::Result<()> {
+    let owner_keys = read_yaml::<KeyPairFile>(&args.owner_keys)?;
+    let timestamp = match args.timestamp {
+        Some(timestamp) => timestamp,
+        None => SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
+    };
+    let delegation = delegate(&args.license_id, &owner_keys, &args.provider_pubkey, timestamp)?;
+    print!("{}", serde_yaml::to_string(&delegation)?);
+    Ok(())
+}
+
+pub fn confirm_delegation(
+    args: &ConfirmDelegationArgs,
+    network: &NetworkArgs,
+) -> anyhow::Result<()> {
+    let provider_keys = read_yaml::<KeyPairFile>(&args.provider_keys)?;
+    let node_keys = read_keys(&args.node_keys)?;
+    let entry = confirm(args, &provider_keys, &node_keys)?;
+
+    let backend_keys = BackendKeyring::from_config(&NetworkProfile::select(network, None)?, &[])?;
+    entry
+        .check_signatures(&backend_keys, &node_keys.wallet.pubkey, &node_keys.bls.pubkey)
+        .map_err(|err| anyhow!("License {}: {err}", entry.license_id))?;
+
+    let section = SignaturesSection { signatures: vec![entry], backend_keys: vec![] };
+    print!("{}", serde_yaml::to_string(&section)?);
+    Ok(())
+}
+
+pub fn verify_signature(args: &VerifySignatureArgs, network: &NetworkArgs) -> anyhow::Result<()> {
+    let section = read_yaml::<SignaturesSection>(&args.input)?;
+    let node_keys = read_keys(&args.node_keys)?;
+
+    let entry = match &args.license_id {
+        Some(license_id) => section
+            .signatures
+            .iter()
+            .find(|sig| &sig.license_id == license_id)
+            .ok_or_else(|| anyhow!("License {license_id} not found in {:?}", args.input))?,
+        None => match section.signatures.as_slice() {
+            [entry] => entry,
+            [] => bail!("No signatures found in {:?}", args.input),
+            _ => {
+                bail!("{:?} contains several signatures, select one with --license-id", args.input)
+            }
+        },
+    };
+
+    // `backend_keys` of the file replace the keys of the network like in config.yaml
+    let network = NetworkProfile::select(network, None)?;
+    let backend_keys = BackendKeyring::from_config(&network, &section.backend_keys)?;
+    let backend_key_id = entry
+        .check_signatures(&backend_keys, &node_keys.wallet.pubkey, &node_keys.bls.pubkey)
+        .map_err(|err| anyhow!("License {}: {err}", entry.license_id))?;
+
+    println!(
+        "License {}: all signatures are valid, backend key {backend_key_id}",
+        entry.license_id
+    );
+    Ok(())
+}
+
+pub fn sign_seeds(args: &SignSeedsArgs) -> anyhow::Result<()> {
+    let authority_keys = read_yaml::<KeyPairFile>(&args.authority_keys)?;
+    // The signature covers the exact file bytes, so the list must not be re-serialized
+    let body = std::fs::read_to_string(&args.input)?;
+    let seeds: Vec<String> = serde_yaml::from_str(&body)
+        .map_err(|err| anyhow!("{:?} is not a seeds list: {err}", args.input))?;
+    let output = signature_path(&args.input);
+    std::fs::write(&output, sign(&authority_keys.signing_key()?, body.as_bytes()))?;
+    println!("Signed {} seeds, signature written to {output:?}", seeds.len());
+    println!("seeds_verifying_key: {}", authority_keys.public);
+    Ok(())
+}
+
+pub fn sign_revocations(args: &SignRevocationsArgs, network: &NetworkArgs) -> anyhow::Result<()> {
+    let backend_keys = read_yaml::<KeyPairFile>(&args.backend_keys)?;
+    // The signature covers the exact file bytes, so the list must not be re-serialized
+    let body = std::fs::read_to_string(&args.input)?;
+    let list: RevocationList = serde_yaml::from_str(&body)
+        .map_err(|err| anyhow!("{:?} is not a revocation list: {err}", args.input))?;
+    let output = signature_path(&args.input);
+    std::fs::write(&output, sign(&backend_keys.signing_key()?, body.as_bytes()))?;
+    println!(
+        "Signed revocation list version {} with {} licenses, signature written to {output:?}",
+        list.version,
+        list.licenses.len()
+    );
+    let network = NetworkProfile::select(network, None)?;
+    let known = BackendKeyring::from_config(&network, &[]).is_ok_and(|keyring| {
+        keyring
+            .keys_at(list.issued_at)
+            .any(|(_, key)| hex::encode(key).eq_ignore_ascii_case(&backend_keys.public))
+    });
+    if !known {
+        println!(
+            "WARNING: the key is not a backend key of network {} valid at `issued_at` {}, \
+             igniters will reject the list unless it is in their `backend_keys`",
+            network.name, list.issued_at
+        );
+    }
+    Ok(())
+}
+
+fn sign(signing_key: &SigningKey, data: &[u8]) -> String {
+    STANDARD.encode(signing_key.sign(data).to_bytes())
+}
+
+fn delegate(
+    license_id: &str,
+    owner_keys: &KeyPairFile,
+    provider_pubkey: &str,
+    timestamp: u64,
+) -> anyhow::Result<Delegation> {
+    let signing_key = owner_keys.signing_key()?;
+    let license_owner_pubkey = hex::encode(signing_key.verifying_key().to_bytes());
+    let data = LicenceSignature::delegation_prepare(
+        license_id,
+        &license_owner_pubkey,
+        provider_pubkey,
+        timestamp,
+    );
+    Ok(Delegation {
+        license_id: license_id.to_string(),
+        license_owner_pubkey,
+        provider_pubkey: provider_pubkey.to_string(),
+        delegation_sig: sign(&signing_key, &data),
+        timestamp,
+    })
+}
+
+fn confirm(
+    args: &ConfirmDelegationArgs,
+    provider_keys: &KeyPairFile,
+    node_keys: &Keys,
+) -> anyhow::Result<LicenceSignature> {
+    let signing_key = provider_keys.signing_key()?;
+    let provider_pubkey = hex::encode(signing_key.verifying_key().to_bytes());
+    let data = LicenceSignature::delegation_confirm_prepare(
+        &args.license_id,
+        &args.license_owner_pubkey,
+        &provider_pubkey,
+        &node_keys.wallet.pubkey,
+        &node_keys.bls.pubkey,
+    );
+    Ok(LicenceSignature {
+        license_id: args.license_id.clone(),
+        license_owner_pubkey: args.license_owner_pubkey.clone(),
+        provider_pubkey,
+        delegation_sig: args.delegation_sig.clone(),
+        delegation_confirm_sig: sign(&signing_key, &data),
+        timestamp: args.timestamp,
+        license_proof_sig: args.license_proof_sig.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::config::BackendKey;
+
+    fn key_pair_file() -> (SigningKey, KeyPairFile) {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let file = KeyPairFile {
+            public: hex::encode(signing_key.verifying_key().to_bytes()),
+            secret: hex::encode(signing_key.to_bytes()),
+        };
+        (signing_key, file)
+    }
+
+    #[test]
+    fn delegation_and_confirmation_pass_all_checks() {
+        let (backend_key, _) = key_pair_file();
+        let (_, owner_keys) = key_pair_file();
+        let (_, provider_keys) = key_pair_file();
+        let node_keys = Keys::generate().unwrap();
+        let license_id = "2aebf602-7503-4572-976c-79f206f9b2c0";
+
+        let delegation =
+            delegate(license_id, &owner_keys, &provider_keys.public, 1736944335).unwrap();
+        let proof_data = LicenceSignature::license_proof_prepare(license_id, &owner_keys.public);
+        let args = ConfirmDelegationArgs {
+            license_id: license_id.to_string(),
+            license_owner_pubkey: delegation.license_owner_pubkey,
+            provider_keys: PathBuf::new(),
+            node_keys: PathBuf::new(),
+            delegation_sig: delegation.delegation_sig,
+            timestamp: delegation.timestamp,
+            license_proof_sig: sign(&backend_key, &proof_data),
+        };
+
+        let entry = confirm(&args, &provider_keys, &node_keys).unwrap();
+        let backend_pk = hex::encode(backend_key.verifying_key().to_bytes());
+        let backend_keys = BackendKeyring::new(vec![BackendKey::new("test", &backend_pk)]).unwrap();
+        assert_eq!(
+            entry
+                .check_signatures(&backend_keys, &node_keys.wallet.pubkey, &node_keys.bls.pubkey)
+                .unwrap(),
+            "test"
+        );
+
+        // The confirmation is bound to the node keys
+        let other_node_keys = Keys::generate().unwrap();
+        assert!(entry
+            .check_signatures(
+                &backend_keys,
+                &other_node_keys.wallet.pubkey,
+                &other_node_keys.bls.pubkey
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn mismatched_key_pair_file_is_rejected() {
+        let (_, mut owner_keys) = key_pair_file();
+        owner_keys.public = key_pair_file().1.public;
+        assert!(delegate("license_id", &owner_keys, "provider", 1).is_err());
+    }
+}
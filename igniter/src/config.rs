@@ -1,46 +1,19 @@
+use std::io::Write;
 use std::net::ToSocketAddrs;
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
 use std::path::PathBuf;
 
+use anyhow::anyhow;
 use anyhow::bail;
+use ed25519_dalek::SigningKey;
+use poem_openapi::Object;
+use rand::rngs::OsRng;
+use rand::RngCore;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 
-lazy_static::lazy_static! {
-    pub static ref DEV_MODE: bool = {
-        std::env::var("DEV_MODE")
-            .map(|val| val.eq_ignore_ascii_case("true"))
-            .unwrap_or(false)
-    };
-
-    pub static ref BACKEND_VERIFYING_KEY:  &'static str = {
-        if *DEV_MODE {
-            println!("Running in dev mode");
-            "ee99af158c8b50f6bee3360615e08db957bd898568638f308d8f320cf4e37638"
-        } else {
-            println!("Running in production mode");
-            "75631f108a226740a8649ff7946bf19d2884c373615d27f5b6d1863b5d97adf3"
-        }
-    };
-    pub static ref IGNITER_IMAGE:  &'static str = {
-        if *DEV_MODE {
-            "docker.gosh.sh/acki-nacki-igniter-pre-release"
-        } else {
-            "teamgosh/acki-nacki-igniter"
-        }
-    };
-
-    pub static ref IGNITER_SEEDS:  &'static str = {
-        if *DEV_MODE {
-            "https://raw.githubusercontent.com/gosh-sh/acki-nacki-igniter-seeds/refs/heads/main/seeds.yaml"
-        } else {
-            "https://raw.githubusercontent.com/ackinacki/acki-nacki-igniter-seeds/refs/heads/main/seeds.yaml"
-        }
-    };
-
-}
-
 macro_rules! hide_secrets_fmt {
     ($self:ident, $f:ident, $name:literal, [$($field:ident),*]) => {
         write!(
@@ -62,9 +35,17 @@ macro_rules! hide_secrets_fmt {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
-    // Chitchat cluster id for gossip
-    #[serde(default = "default_cluster_id")]
-    pub cluster_id: String,
+    // Network profile, see `network`. `--network` takes precedence
+    #[serde(default)]
+    pub network: Option<String>,
+
+    // File with custom network profiles, `--networks-file` takes precedence
+    #[serde(default)]
+    pub networks_file: Option<PathBuf>,
+
+    // Chitchat cluster id for gossip, overrides the cluster id of the network
+    #[serde(default)]
+    pub cluster_id: Option<String>,
 
     #[serde(default)]
     pub proxies: Vec<ProxyConfig>,
@@ -82,9 +63,28 @@ pub struct Config {
     #[serde(deserialize_with = "deserialize_addr", default = "default_advertise_addr")]
     pub advertise_addr: SocketAddr,
 
+    // Seeds are taken from the first non-empty source, see `seeds::SeedSources`:
+    // `seeds`, `seeds_file`, the seeds url of the network, `seeds_mirrors`, the mirrors of the
+    // network and finally `seeds_cache`
     #[serde(default)]
     pub seeds: Vec<String>,
 
+    #[serde(default)]
+    pub seeds_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub seeds_mirrors: Vec<String>,
+
+    // Seeds from the last successful download are stored here
+    #[serde(default)]
+    pub seeds_cache: Option<PathBuf>,
+
+    // Hex encoded ed25519 public key of the seeds authority, replaces the key of the network.
+    // With a key downloaded and cached seeds lists must come with a valid detached signature
+    // (`<url>.sig`)
+    #[serde(default)]
+    pub seeds_verifying_key: Option<String>,
+
     #[serde(default)]
     pub node_id: Option<String>,
 
@@ -94,14 +94,121 @@ pub struct Config {
     #[serde(default)]
     pub signatures: Vec<LicenceSignature>,
 
+    // What to do when some of `signatures` fail validation
+    #[serde(default)]
+    pub invalid_licenses: InvalidLicensesPolicy,
+
+    // Limits for the delegation timestamps of the own licenses on top of the policy of the
+    // network, the other nodes are checked with the policy of the network only
+    #[serde(default)]
+    pub timestamp_policy: TimestampPolicy,
+
+    // Backend keys that replace the keys of the network, see `backend_keys`
+    #[serde(default)]
+    pub backend_keys: Vec<BackendKey>,
+
+    // Signed list of the licenses revoked by the backend, see `revocations`
+    #[serde(default)]
+    pub revocations: RevocationsConfig,
+
+    // Reaction to the revocation of the own licenses, see `revoked_license_watcher`
+    #[serde(default)]
+    pub license_watcher: LicenseWatcherConfig,
+
+    // Number of live nodes, including this one, required by the `/ready` endpoint
+    #[serde(default = "default_min_live_nodes")]
+    pub min_live_nodes: usize,
+
     pub auto_update: bool,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidLicensesPolicy {
+    // Refuse to start
+    #[default]
+    Reject,
+    // Gossip only the valid licenses and warn about the rest
+    Skip,
+}
+
+// Delegation timestamps are in seconds since the Unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimestampPolicy {
+    // A delegation dated later than now plus this many seconds is rejected, otherwise it would
+    // outrank all later re-delegations of the license
+    #[serde(default = "default_max_future_secs")]
+    pub max_future_secs: u64,
+    // Delegations dated earlier are rejected
+    #[serde(default)]
+    pub min_timestamp: Option<u64>,
+}
+
+impl Default for TimestampPolicy {
+    fn default() -> Self {
+        TimestampPolicy { max_future_secs: default_max_future_secs(), min_timestamp: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackendKey {
+    pub key_id: String,
+    // Hex encoded ed25519 public key
+    pub pubkey: String,
+    // Window of the delegation timestamps the key verifies, in seconds since the Unix epoch
+    #[serde(default)]
+    pub valid_from: Option<u64>,
+    #[serde(default)]
+    pub valid_until: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevocationsConfig {
+    // Local list, `<file>.sig` must contain its signature
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    // Used if `file` is not set, the signature is downloaded from `<url>.sig`
+    #[serde(default)]
+    pub url: Option<String>,
+    // How often the list is loaded again
+    #[serde(default = "default_revocations_refresh_secs")]
+    pub refresh_secs: u64,
+    // Publish the active list in gossip and take newer lists from the other nodes
+    #[serde(default = "default_revocations_gossip")]
+    pub gossip: bool,
+}
+
+impl Default for RevocationsConfig {
+    fn default() -> Self {
+        RevocationsConfig {
+            file: None,
+            url: None,
+            refresh_secs: default_revocations_refresh_secs(),
+            gossip: default_revocations_gossip(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LicenseWatcherConfig {
+    // Last known status of the own licenses, so a restart doesn't report them again
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+    // Remove revoked licenses from the own `signatures` and `licenses` gossip keys
+    #[serde(default)]
+    pub drop_revoked: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Keys {
     pub wallet: WalletConfig,
     pub bls: BlsConfig,
 }
+impl std::fmt::Display for Keys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Keys {{ wallet: {}, bls: {} }}", self.wallet, self.bls)
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct WalletConfig {
@@ -138,6 +245,76 @@ pub struct BlsConfig {
     pub rnd: String,
 }
 
+impl Keys {
+    pub fn generate() -> anyhow::Result<Self> {
+        Ok(Keys { wallet: WalletConfig::generate(), bls: BlsConfig::generate()? })
+    }
+}
+
+impl WalletConfig {
+    // Same format as `tvm-cli getkeypair`: hex encoded 32 byte ed25519 public and secret keys
+    pub fn generate() -> Self {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        WalletConfig {
+            pubkey: hex::encode(signing_key.verifying_key().to_bytes()),
+            secret: hex::encode(signing_key.to_bytes()),
+        }
+    }
+
+    pub fn signing_key(&self) -> anyhow::Result<SigningKey> {
+        KeyPairFile { public: self.pubkey.clone(), secret: self.secret.clone() }.signing_key()
+    }
+}
+
+impl BlsConfig {
+    // Same format as `node-helper bls`: `rnd` is the key material the secret key is derived from
+    pub fn generate() -> anyhow::Result<Self> {
+        let mut rnd = [0u8; 32];
+        OsRng.fill_bytes(&mut rnd);
+        let secret = blst::min_pk::SecretKey::key_gen(&rnd, &[])
+            .map_err(|err| anyhow!("BLS key generation failed: {err:?}"))?;
+        Ok(BlsConfig {
+            pubkey: hex::encode(secret.sk_to_pk().compress()),
+            secret: hex::encode(secret.to_bytes()),
+            rnd: hex::encode(rnd),
+        })
+    }
+}
+
+// Key pair file in the `tvm-cli getkeypair` format
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KeyPairFile {
+    pub public: String,
+    pub secret: String,
+}
+impl std::fmt::Debug for KeyPairFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        hide_secrets_fmt!(self, f, "KeyPairFile", [public, secret])
+    }
+}
+
+impl KeyPairFile {
+    pub fn signing_key(&self) -> anyhow::Result<SigningKey> {
+        let secret = hex::decode(&self.secret)?;
+        let secret: [u8; 32] =
+            secret.try_into().map_err(|_| anyhow!("Secret key must be 32 bytes long"))?;
+        let signing_key = SigningKey::from_bytes(&secret);
+        if hex::encode(signing_key.verifying_key().to_bytes()) != self.public.to_lowercase() {
+            bail!("Public key {} does not match the secret key", self.public);
+        }
+        Ok(signing_key)
+    }
+}
+
+// Only the `signatures` section of config.yaml, other fields are ignored
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SignaturesSection {
+    #[serde(default)]
+    pub signatures: Vec<LicenceSignature>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backend_keys: Vec<BackendKey>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProxyConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -146,7 +323,7 @@ pub struct ProxyConfig {
     pub cert: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Object)]
 pub struct LicenceSignature {
     pub license_id: String,
     pub license_owner_pubkey: String,
@@ -164,6 +341,10 @@ impl HasTimestampAndId for LicenceSignature {
     fn get_timestamp(&self) -> u64 {
         self.timestamp
     }
+
+    fn get_provider(&self) -> String {
+        self.provider_pubkey.clone()
+    }
 }
 
 pub fn read_yaml<T: DeserializeOwned>(config_path: impl AsRef<Path>) -> anyhow::Result<T> {
@@ -177,12 +358,46 @@ pub fn read_yaml<T: DeserializeOwned>(config_path: impl AsRef<Path>) -> anyhow::
     Ok(config)
 }
 
+// Writes a new file readable only by the owner. Fails if the file already exists.
+pub fn write_yaml_new<T: Serialize>(path: impl AsRef<Path>, value: &T) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let Some(path_str) = path.as_os_str().to_str() else {
+        bail!("Invalid path {:?}", path);
+    };
+    let expanded = PathBuf::from(shellexpand::tilde(path_str).into_owned());
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&expanded)
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                anyhow!("File {expanded:?} already exists, refusing to overwrite it")
+            }
+            _ => anyhow!("Can't create file {expanded:?}: {err}"),
+        })?;
+    file.write_all(serde_yaml::to_string(value)?.as_bytes())?;
+    Ok(())
+}
+
 fn default_interval() -> u64 {
     500
 }
 
-fn default_cluster_id() -> String {
-    env!("CARGO_PKG_NAME").to_string()
+fn default_max_future_secs() -> u64 {
+    600
+}
+
+fn default_revocations_refresh_secs() -> u64 {
+    300
+}
+
+fn default_revocations_gossip() -> bool {
+    true
+}
+
+fn default_min_live_nodes() -> usize {
+    2
 }
 
 fn default_listen_addr() -> SocketAddr {
@@ -218,6 +433,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
     use super::*;
     #[test]
     fn read_config_failed() {
@@ -240,6 +457,25 @@ mod tests {
         assert_eq!(cfg.signatures[0].license_id, "license_id_0");
     }
 
+    #[test]
+    fn generated_keys_are_written_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.yaml");
+        let keys = Keys::generate().expect("Key generation must not fail");
+        assert_eq!(keys.wallet.pubkey.len(), 64);
+        assert_eq!(keys.bls.pubkey.len(), 96);
+
+        write_yaml_new(&path, &keys).expect("File does not exist yet");
+        assert!(write_yaml_new(&path, &Keys::generate().unwrap()).is_err());
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        let read_back = read_yaml::<Keys>(&path).expect("File exists and valid");
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(read_back.wallet.pubkey, keys.wallet.pubkey);
+        assert_eq!(read_back.bls.rnd, keys.bls.rnd);
+    }
+
     #[test]
     fn read_config_no_proxies_success() {
         let cfg = read_yaml::<Config>(Path::new("./tests/config-no-proxies.yaml"))
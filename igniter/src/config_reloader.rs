@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::bail;
+use chitchat::ChitchatRef;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
+use tokio::task::JoinHandle;
+
+use crate::cli::Params;
+use crate::config::read_yaml;
+use crate::config::Config;
+use crate::revoked_license_watcher::DroppedLicenses;
+use crate::ZerostateKeys;
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Only these keys are updated in the running gossip state
+const RELOADABLE_KEYS: [ZerostateKeys; 3] =
+    [ZerostateKeys::Signatures, ZerostateKeys::Licenses, ZerostateKeys::Proxies];
+
+// Config fields that affect the reloadable keys
+const RELOADABLE_FIELDS: [&str; 3] = ["signatures", "invalid_licenses", "proxies"];
+
+// Applies changes of config.yaml to the running node without a restart, so the node keeps
+// its chitchat id. The file is checked on SIGHUP and every CONFIG_POLL_INTERVAL.
+pub struct ConfigReloader {
+    chitchat: ChitchatRef,
+    config_path: PathBuf,
+    params: Params,
+    // Config as it was last read from the file, `params.config` has resolved seeds
+    file_config: Option<Config>,
+    contents: Vec<u8>,
+    applied: HashMap<String, String>,
+    // Revoked licenses the license watcher has removed from gossip, they are not published again
+    dropped: DroppedLicenses,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        chitchat: ChitchatRef,
+        params: Params,
+        config_path: PathBuf,
+        dropped: DroppedLicenses,
+    ) -> anyhow::Result<Self> {
+        let applied = params.to_gossip()?.into_iter().collect();
+        let contents = std::fs::read(&config_path).unwrap_or_default();
+        let file_config = read_yaml::<Config>(&config_path).ok();
+        Ok(Self { chitchat, config_path, params, file_config, contents, applied, dropped })
+    }
+
+    pub fn run(mut self) -> anyhow::Result<JoinHandle<()>> {
+        let mut hangup = signal(SignalKind::hangup())?;
+        Ok(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+            loop {
+                let forced = tokio::select! {
+                    _ = hangup.recv() => true,
+                    _ = interval.tick() => false,
+                };
+                if !forced && !self.file_changed() {
+                    continue;
+                }
+                tracing::info!("Reloading config {:?}", self.config_path);
+                match self.reload() {
+                    Ok(keys) if keys.is_empty() => tracing::info!("Gossip state is up to date"),
+                    Ok(keys) => tracing::info!("Gossip state updated: {}", keys.join(", ")),
+                    Err(err) => {
+                        tracing::error!("New config is rejected, keeping the current state: {err}")
+                    }
+                }
+            }
+        }))
+    }
+
+    fn file_changed(&mut self) -> bool {
+        match std::fs::read(&self.config_path) {
+            Ok(contents) if contents != self.contents => {
+                self.contents = contents;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Returns the updated gossip keys, the state is left untouched on error
+    pub fn reload(&mut self) -> anyhow::Result<Vec<String>> {
+        let file_config = read_yaml::<Config>(&self.config_path)?;
+        let mut config = file_config.clone();
+        config.seeds = self.params.config.seeds.clone();
+        let params = Params { config, ..self.params.clone() };
+        let mut key_values = params.to_gossip()?;
+
+        if let Some(previous) = &self.file_config {
+            let fields = restart_required_fields(previous, &file_config)?;
+            if !fields.is_empty() {
+                tracing::warn!("Changes of {} are applied after restart only", fields.join(", "));
+            }
+        }
+
+        let reloadable = RELOADABLE_KEYS.map(|key| key.to_string());
+        let changed: Vec<(String, String)> = {
+            // The dropped licenses can't change until the values are set
+            let mut chitchat = self.chitchat.lock();
+            self.dropped.filter(&mut key_values)?;
+            let changed: Vec<(String, String)> = key_values
+                .into_iter()
+                .filter(|(key, value)| {
+                    reloadable.contains(key) && self.applied.get(key) != Some(value)
+                })
+                .collect();
+            let node_state = chitchat.self_node_state();
+            for (key, value) in &changed {
+                node_state.set(key, value);
+            }
+            changed
+        };
+
+        let keys = changed.iter().map(|(key, _)| key.clone()).collect();
+        self.applied.extend(changed);
+        self.params = params;
+        self.file_config = Some(file_config);
+        Ok(keys)
+    }
+}
+
+fn restart_required_fields(previous: &Config, current: &Config) -> anyhow::Result<Vec<String>> {
+    let (serde_json::Value::Object(previous), serde_json::Value::Object(current)) =
+        (serde_json::to_value(previous)?, serde_json::to_value(current)?)
+    else {
+        bail!("Config is not serialized to an object");
+    };
+    Ok(current
+        .iter()
+        .filter(|(field, value)| {
+            !RELOADABLE_FIELDS.contains(&field.as_str()) && previous.get(*field) != Some(*value)
+        })
+        .map(|(field, _)| field.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Keys;
+    use crate::config::ProxyConfig;
+    use crate::gossip::test_chitchat;
+    use crate::network::NetworkProfile;
+
+    const CONFIG: &str = r#"
+auto_update: false
+proxies:
+  - socket_address: "10.122.4.4:8925"
+signatures:
+  - license_id: "5e0d534d-98fd-4024-87b8-8c45414f6e9a"
+    license_owner_pubkey: "37d545d8725f290b1dcff6e06ad7649a6264249a3202354330bb47da90c7b41f"
+    provider_pubkey: "8e962b104119b17ab09e9aa91ff17e5816f65bb66daa6c14b8ca130f4f0bfcc0"
+    license_proof_sig: "c6F8qZ52LNeLLKdrVll5F1/U9eGGPUzJMZw7JcKWSzbO/DmmNXkWlDW+k3GwD1giLMxUPbjPzegqYoLoKOThAg=="
+    delegation_sig: "00lhq1wiiCs10ISYz2AkrZX9M0TU4YaNba3wG7oCeEFUP6uOav5kGesqntIQ+AKL5Y3nkVw+redFxQbOEuM1Dg=="
+    delegation_confirm_sig: "QCd1iMgEUOd7unQ2Qi1v8EyLNIwphFc2hct+/cAsdAT7VUxUNlNmhbo6SKNHyvX5OKnrpIBf2d5JgQNZj3ueDw=="
+    timestamp: 1744375960
+"#;
+
+    const KEYS: &str = r#"
+wallet:
+  pubkey: "3ef72c59a33ba75a484cfb126bd9e55db267cbd944110374d0b78a9e474c6c87"
+  secret: "def"
+bls:
+  pubkey: "8cf7d141cade81a44c8bc58a02b0448e85e77d47d9c644adfe3512d3c5fcdc2a028cfb96aff704a70f2cce27c96cd706"
+  secret: "def"
+  rnd: "abc"
+"#;
+
+    fn proxies(chitchat: &ChitchatRef) -> String {
+        let mut chitchat = chitchat.lock();
+        chitchat.self_node_state().get(&ZerostateKeys::Proxies.to_string()).unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn changed_keys_are_applied_and_invalid_config_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(&config_path, CONFIG).unwrap();
+        let params = Params {
+            config: read_yaml(&config_path).unwrap(),
+            network: NetworkProfile::devnet(),
+            docker_socket: None,
+            docker_config: None,
+            keys: serde_yaml::from_str::<Keys>(KEYS).unwrap(),
+        };
+        let handle = test_chitchat("test", 10000, params.to_gossip().unwrap()).await;
+        let chitchat = handle.chitchat();
+        let dropped = DroppedLicenses::default();
+        let mut reloader =
+            ConfigReloader::new(chitchat.clone(), params, config_path.clone(), dropped.clone())
+                .unwrap();
+        assert!(reloader.reload().unwrap().is_empty());
+
+        let new_proxy = "10.122.4.5:8925";
+        std::fs::write(&config_path, CONFIG.replace("10.122.4.4:8925", new_proxy)).unwrap();
+        assert_eq!(reloader.reload().unwrap(), vec![ZerostateKeys::Proxies.to_string()]);
+        let expected: Vec<ProxyConfig> = serde_json::from_str(&proxies(&chitchat)).unwrap();
+        assert_eq!(expected[0].socket_address, Some(new_proxy.parse().unwrap()));
+
+        // A broken signature fails validation and the previous state stays in gossip
+        let broken = CONFIG.replace("10.122.4.4:8925", "10.122.4.6:8925").replace("c6F8", "AAAA");
+        std::fs::write(&config_path, broken).unwrap();
+        assert!(reloader.reload().is_err());
+        assert!(proxies(&chitchat).contains(new_proxy));
+    }
+}
@@ -0,0 +1,196 @@
+// Agreement of the cluster on the zerostate export. Every igniter gossips the SHA-256 of its
+// canonical export under `export_hash`; the live nodes are grouped by the hash they report.
+// Zerostate can be generated once all live nodes report the same hash for a while.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use chitchat::ChitchatId;
+use chitchat::ChitchatRef;
+use poem_openapi::Object;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+use crate::gossip::set_if_changed;
+use crate::open_api::export::SignedExport;
+use crate::open_api::export::ZerostateExport;
+use crate::open_api::routes::NodeId;
+use crate::open_api::routes::VerifiedNodeState;
+use crate::verification_cache::VerificationCache;
+use crate::ZerostateKeys;
+
+const CONSENSUS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct HashGroup {
+    // None for the nodes that don't report a hash, e.g. running an older igniter
+    pub hash: Option<String>,
+    pub nodes: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct ConsensusReport {
+    pub own_hash: Option<String>,
+    pub majority_hash: Option<String>,
+    // All live nodes report the majority hash
+    pub converged: bool,
+    pub live_nodes: usize,
+    // Seconds since the majority hash was last changed
+    pub majority_stable_secs: u64,
+    // Largest group first
+    pub groups: Vec<HashGroup>,
+    pub disagreeing_nodes: Vec<NodeId>,
+}
+
+// Majority hash as it was last seen and since when
+type Majority = Option<(Option<String>, Instant)>;
+
+pub struct ConsensusMonitor {
+    chitchat: ChitchatRef,
+    majority: Arc<Mutex<Majority>>,
+    task: JoinHandle<()>,
+}
+
+impl ConsensusMonitor {
+    // Publishes the own export hash and follows the majority every CONSENSUS_INTERVAL
+    pub fn spawn(chitchat: ChitchatRef, verified: VerificationCache) -> Self {
+        let majority = Arc::<Mutex<Majority>>::default();
+        let task = tokio::spawn({
+            let chitchat = chitchat.clone();
+            let majority = majority.clone();
+            async move {
+                let mut interval = tokio::time::interval(CONSENSUS_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    publish_export_hash(&chitchat, &verified.get().verified_state);
+                    let groups = group_live_nodes(&chitchat);
+                    update_majority(&majority, majority_hash(&groups));
+                }
+            }
+        });
+        ConsensusMonitor { chitchat, majority, task }
+    }
+
+    pub fn report(&self) -> ConsensusReport {
+        let own_hash = {
+            let mut chitchat = self.chitchat.lock();
+            chitchat.self_node_state().get(&ZerostateKeys::ExportHash.to_string()).map(String::from)
+        };
+        let groups = group_live_nodes(&self.chitchat);
+        let majority_hash = majority_hash(&groups);
+        let majority_stable_secs = update_majority(&self.majority, majority_hash.clone());
+
+        let live_nodes = groups.iter().map(|group| group.nodes.len()).sum();
+        let disagreeing_nodes: Vec<NodeId> = groups
+            .iter()
+            .filter(|group| majority_hash.is_none() || group.hash != majority_hash)
+            .flat_map(|group| group.nodes.clone())
+            .collect();
+        ConsensusReport {
+            own_hash,
+            converged: majority_hash.is_some() && disagreeing_nodes.is_empty(),
+            majority_hash,
+            live_nodes,
+            majority_stable_secs: majority_stable_secs.as_secs(),
+            groups,
+            disagreeing_nodes,
+        }
+    }
+}
+
+impl Drop for ConsensusMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn publish_export_hash(chitchat: &ChitchatRef, verified_state: &[VerifiedNodeState]) {
+    let hash = SignedExport::new(&ZerostateExport::new(verified_state), None).sha256;
+
+    set_if_changed(chitchat, &ZerostateKeys::ExportHash.to_string(), hash);
+}
+
+fn group_live_nodes(chitchat: &ChitchatRef) -> Vec<HashGroup> {
+    let key = ZerostateKeys::ExportHash.to_string();
+    let chitchat = chitchat.lock();
+    let reports: Vec<(ChitchatId, Option<String>)> = chitchat
+        .live_nodes()
+        .map(|id| {
+            let hash = chitchat.node_state(id).and_then(|state| state.get(&key)).map(String::from);
+            (id.clone(), hash)
+        })
+        .collect();
+    group_by_hash(reports)
+}
+
+fn group_by_hash(reports: Vec<(ChitchatId, Option<String>)>) -> Vec<HashGroup> {
+    let mut groups = BTreeMap::<Option<String>, Vec<NodeId>>::new();
+    for (id, hash) in reports {
+        groups.entry(hash).or_default().push(NodeId::from(&id));
+    }
+    let mut groups: Vec<HashGroup> =
+        groups.into_iter().map(|(hash, nodes)| HashGroup { hash, nodes }).collect();
+    // Ties are broken by the hash, so every node picks the same majority
+    groups.sort_by(|a, b| b.nodes.len().cmp(&a.nodes.len()).then_with(|| a.hash.cmp(&b.hash)));
+    groups
+}
+
+// The largest group of the nodes that report a hash
+fn majority_hash(groups: &[HashGroup]) -> Option<String> {
+    groups.iter().find_map(|group| group.hash.clone())
+}
+
+// Returns for how long the majority hash has not changed
+fn update_majority(majority: &Mutex<Majority>, hash: Option<String>) -> Duration {
+    let mut majority = majority.lock().expect("majority lock");
+    match majority.as_ref() {
+        Some((current, since)) if *current == hash => since.elapsed(),
+        _ => {
+            *majority = Some((hash, Instant::now()));
+            Duration::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(port: u16, hash: Option<&str>) -> (ChitchatId, Option<String>) {
+        let id = ChitchatId::new(format!("node-{port}"), 0, ([127, 0, 0, 1], port).into());
+        (id, hash.map(String::from))
+    }
+
+    #[test]
+    fn largest_group_is_the_majority() {
+        let groups = group_by_hash(vec![
+            report(1, Some("b")),
+            report(2, None),
+            report(3, Some("a")),
+            report(4, None),
+            report(5, None),
+            report(6, Some("b")),
+        ]);
+        let hashes: Vec<Option<&str>> = groups.iter().map(|g| g.hash.as_deref()).collect();
+        assert_eq!(hashes, [None, Some("b"), Some("a")]);
+        // Nodes without a hash can't form the majority
+        assert_eq!(majority_hash(&groups).as_deref(), Some("b"));
+
+        let tie = group_by_hash(vec![report(1, Some("b")), report(2, Some("a"))]);
+        assert_eq!(majority_hash(&tie).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn stability_is_reset_when_the_majority_changes() {
+        let majority = Mutex::new(None);
+        assert_eq!(update_majority(&majority, Some("a".to_string())), Duration::ZERO);
+        *majority.lock().unwrap() =
+            Some((Some("a".to_string()), Instant::now() - CONSENSUS_INTERVAL));
+        assert!(update_majority(&majority, Some("a".to_string())) >= CONSENSUS_INTERVAL);
+        assert_eq!(update_majority(&majority, Some("b".to_string())), Duration::ZERO);
+    }
+}
@@ -0,0 +1,220 @@
+// Passphrase protected keys.yaml: the plaintext file is encrypted with XChaCha20-Poly1305
+// under a key derived from the passphrase with Argon2id. KDF parameters, salt and nonce
+// are stored next to the ciphertext, so they can be changed without breaking old files.
+
+use std::io::IsTerminal;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use argon2::Algorithm;
+use argon2::Argon2;
+use argon2::Version;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::KeyInit;
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::XNonce;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config::Keys;
+
+pub const PASSPHRASE_ENV: &str = "IGNITER_KEYS_PASSPHRASE";
+pub const PASSPHRASE_FILE_ENV: &str = "IGNITER_KEYS_PASSPHRASE_FILE";
+
+const FORMAT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KdfParams {
+    // Memory size in KiB
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        let params = argon2::Params::default();
+        KdfParams { m_cost: params.m_cost(), t_cost: params.t_cost(), p_cost: params.p_cost() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedKeys {
+    pub version: u32,
+    pub kdf: KdfParams,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+// Top level of an encrypted keys file, the key tells it apart from a plaintext keys.yaml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedKeysFile {
+    pub encrypted_keys: EncryptedKeys,
+}
+
+impl EncryptedKeys {
+    pub fn encrypt(keys: &Keys, passphrase: &str, kdf: KdfParams) -> anyhow::Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let cipher = cipher(passphrase, &salt, kdf)?;
+        let plaintext = serde_yaml::to_string(keys)?;
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_bytes())
+            .map_err(|_| anyhow!("Keys encryption failed"))?;
+        Ok(EncryptedKeys {
+            version: FORMAT_VERSION,
+            kdf,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> anyhow::Result<Keys> {
+        if self.version != FORMAT_VERSION {
+            bail!("Unsupported encrypted keys version {}", self.version);
+        }
+        let salt = hex::decode(&self.salt)?;
+        let nonce = hex::decode(&self.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            bail!("Nonce must be {NONCE_LEN} bytes long");
+        }
+        let ciphertext = STANDARD.decode(&self.ciphertext)?;
+
+        let cipher = cipher(passphrase, &salt, self.kdf)?;
+        // A wrong passphrase and a damaged file look the same to the AEAD
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| anyhow!("Can't decrypt keys: wrong passphrase or damaged file"))?;
+        Ok(serde_yaml::from_slice(&plaintext)?)
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8], kdf: KdfParams) -> anyhow::Result<XChaCha20Poly1305> {
+    let params = argon2::Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32))
+        .map_err(|err| anyhow!("Invalid KDF parameters: {err}"))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow!("Key derivation failed: {err}"))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+// Reads a plaintext or an encrypted keys file, the passphrase is taken from the environment
+pub fn read_keys(path: &Path) -> anyhow::Result<Keys> {
+    read_keys_with(path, &PassphraseSource::from_env())
+}
+
+pub fn read_keys_with(path: &Path, passphrase: &PassphraseSource) -> anyhow::Result<Keys> {
+    let expanded = shellexpand::tilde(&path.to_string_lossy()).into_owned();
+    let contents = std::fs::read_to_string(&expanded)?;
+    let value: serde_yaml::Value = serde_yaml::from_str(&contents)?;
+    if value.get("encrypted_keys").is_none() {
+        return Ok(serde_yaml::from_value(value)?);
+    }
+    let file: EncryptedKeysFile = serde_yaml::from_value(value)?;
+    let passphrase = passphrase.get(&format!("Passphrase for {path:?}: "), false)?;
+    file.encrypted_keys.decrypt(&passphrase)
+}
+
+// Where the passphrase comes from: the value, then the file and finally the terminal
+#[derive(Debug, Clone, Default)]
+pub struct PassphraseSource {
+    pub passphrase: Option<String>,
+    pub passphrase_file: Option<PathBuf>,
+}
+
+impl PassphraseSource {
+    // IGNITER_KEYS_PASSPHRASE and IGNITER_KEYS_PASSPHRASE_FILE
+    pub fn from_env() -> Self {
+        PassphraseSource {
+            passphrase: std::env::var(PASSPHRASE_ENV).ok(),
+            passphrase_file: std::env::var_os(PASSPHRASE_FILE_ENV).map(PathBuf::from),
+        }
+    }
+
+    pub fn get(&self, prompt: &str, confirm: bool) -> anyhow::Result<String> {
+        if let Some(passphrase) = &self.passphrase {
+            return Ok(passphrase.clone());
+        }
+        if let Some(path) = &self.passphrase_file {
+            let passphrase = std::fs::read_to_string(path)
+                .map_err(|err| anyhow!("Can't read passphrase file {path:?}: {err}"))?;
+            return Ok(passphrase.trim_end_matches(['\r', '\n']).to_string());
+        }
+        if !std::io::stdin().is_terminal() {
+            bail!("Keys are encrypted, set {PASSPHRASE_ENV} or {PASSPHRASE_FILE_ENV}");
+        }
+        let passphrase = rpassword::prompt_password(prompt)?;
+        if confirm && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+            bail!("Passphrases do not match");
+        }
+        Ok(passphrase)
+    }
+}
+
+// The passphrase is taken from IGNITER_KEYS_PASSPHRASE, then from the file named by
+// IGNITER_KEYS_PASSPHRASE_FILE and finally asked on the terminal
+pub fn passphrase(prompt: &str, confirm: bool) -> anyhow::Result<String> {
+    PassphraseSource::from_env().get(prompt, confirm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap parameters, the defaults are too slow for unoptimized builds
+    const TEST_KDF: KdfParams = KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 };
+
+    #[test]
+    fn keys_survive_encryption_round_trip() {
+        let keys = Keys::generate().unwrap();
+        let encrypted = EncryptedKeys::encrypt(&keys, "correct horse", TEST_KDF).unwrap();
+        assert!(!encrypted.ciphertext.contains(&keys.wallet.secret));
+
+        let decrypted = encrypted.decrypt("correct horse").unwrap();
+        assert_eq!(decrypted.wallet.secret, keys.wallet.secret);
+        assert_eq!(decrypted.bls.secret, keys.bls.secret);
+        assert_eq!(decrypted.bls.rnd, keys.bls.rnd);
+
+        assert!(encrypted.decrypt("wrong horse").is_err());
+    }
+
+    #[test]
+    fn plaintext_and_encrypted_files_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("keys.yaml");
+        let encrypted = dir.path().join("keys.enc.yaml");
+        let keys = Keys::generate().unwrap();
+        std::fs::write(&plain, serde_yaml::to_string(&keys).unwrap()).unwrap();
+        let file = EncryptedKeysFile {
+            encrypted_keys: EncryptedKeys::encrypt(&keys, "passphrase", TEST_KDF).unwrap(),
+        };
+        std::fs::write(&encrypted, serde_yaml::to_string(&file).unwrap()).unwrap();
+
+        let source = PassphraseSource::default();
+        assert_eq!(read_keys_with(&plain, &source).unwrap().wallet.secret, keys.wallet.secret);
+
+        let passphrase_file = dir.path().join("passphrase");
+        std::fs::write(&passphrase_file, "passphrase\n").unwrap();
+        let source =
+            PassphraseSource { passphrase: None, passphrase_file: Some(passphrase_file.clone()) };
+        assert_eq!(read_keys_with(&encrypted, &source).unwrap().bls.secret, keys.bls.secret);
+
+        // The value takes precedence over the file
+        let source = PassphraseSource { passphrase: Some("wrong".to_string()), ..source };
+        assert!(read_keys_with(&encrypted, &source).is_err());
+    }
+}
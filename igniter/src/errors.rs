@@ -26,9 +26,21 @@ pub enum IgniterError {
     #[error("Duplicate license id: {0}")]
     DuplicateLicenseId(String),
 
+    #[error("Delegation timestamp {timestamp} is more than {max_future_secs}s in the future")]
+    FutureTimestamp { timestamp: u64, max_future_secs: u64 },
+
+    #[error("Delegation timestamp {timestamp} is earlier than the minimum {min_timestamp}")]
+    StaleTimestamp { timestamp: u64, min_timestamp: u64 },
+
     #[error("Invalid backend public key")]
     InvalidBackendKey,
 
+    #[error("No backend key is valid for delegation timestamp {timestamp}")]
+    NoBackendKey { timestamp: u64 },
+
+    #[error("Network {0} has no backend keys, set `backend_keys` in config.yaml")]
+    NoNetworkBackendKeys(String),
+
     #[error("{0}")]
     Other(#[from] anyhow::Error),
 }
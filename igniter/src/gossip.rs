@@ -2,7 +2,9 @@
 //
 
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 use std::time::SystemTime;
 
 use chitchat::spawn_chitchat;
@@ -15,14 +17,19 @@ use chitchat::FailureDetectorConfig;
 use cool_id_generator::Size;
 use poem::listener::TcpListener;
 use poem::middleware::Cors;
+use poem::Endpoint;
 use poem::EndpointExt;
 use poem::Route;
 use poem::Server;
 use poem_openapi::OpenApiService;
+use poem_openapi::OperationId;
 use serde::Deserialize;
 use serde::Serialize;
 use tokio::task::JoinHandle;
 
+use crate::cli::Params;
+use crate::verification_cache::VerificationCache;
+
 static DEFAULT_GOSSIP_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,20 +45,56 @@ pub struct SetKeyValueResponse {
     pub status: bool,
 }
 
+// Sets a key of the own node state. Setting the same value would still bump the version and cause
+// extra gossip, so an unchanged value is skipped.
+pub fn set_if_changed(chitchat: &ChitchatRef, key: &str, value: String) {
+    let mut chitchat = chitchat.lock();
+    let node_state = chitchat.self_node_state();
+    if node_state.get(key) != Some(value.as_str()) {
+        node_state.set(key, value);
+    }
+}
+
+// Chitchat on an in-memory transport for the tests that read or write the gossip state
+#[cfg(test)]
+pub(crate) async fn test_chitchat(
+    cluster_id: &str,
+    port: u16,
+    key_values: Vec<(String, String)>,
+) -> ChitchatHandle {
+    let advertise_addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let config = ChitchatConfig {
+        cluster_id: cluster_id.to_string(),
+        chitchat_id: ChitchatId::new(cluster_id.to_string(), 0, advertise_addr),
+        gossip_interval: DEFAULT_GOSSIP_INTERVAL,
+        listen_addr: advertise_addr,
+        seed_nodes: vec![],
+        failure_detector_config: FailureDetectorConfig::default(),
+        marked_for_deletion_grace_period: Duration::from_secs(10),
+        catchup_callback: None,
+        extra_liveness_predicate: None,
+    };
+    let transport = chitchat::transport::ChannelTransport::with_mtu(65_507);
+    spawn_chitchat(config, key_values, &transport).await.expect("test chitchat")
+}
+
 fn generate_server_id(public_addr: SocketAddr) -> String {
     let cool_id = cool_id_generator::get_id(Size::Medium);
     format!("server:{public_addr}-{cool_id}")
 }
 
 pub async fn run(
-    listen_addr: SocketAddr,
-    api_addr: SocketAddr,
+    params: &Params,
     transport: impl chitchat::transport::Transport,
-    gossip_advertise_addr: SocketAddr,
-    seeds: Vec<String>,
-    cluster_id: String,
-    initial_key_values: Vec<(String, String)>,
-) -> anyhow::Result<(ChitchatRef, ChitchatHandle, JoinHandle<anyhow::Result<()>>)> {
+) -> anyhow::Result<(ChitchatRef, ChitchatHandle, JoinHandle<anyhow::Result<()>>, VerificationCache)>
+{
+    let initial_key_values = params.to_gossip()?;
+    let listen_addr = params.config.listen_addr;
+    let api_addr = params.config.api_addr;
+    let gossip_advertise_addr = params.config.advertise_addr;
+    let cluster_id = params.cluster_id().to_string();
+    let seeds = params.config.seeds.clone();
+
     let node_id = generate_server_id(gossip_advertise_addr);
     let generation = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
     let chitchat_id = ChitchatId::new(node_id, generation, gossip_advertise_addr);
@@ -70,7 +113,34 @@ pub async fn run(
     tracing::info!("Starting gossip server on {gossip_advertise_addr}");
     let chitchat_handle = spawn_chitchat(config, initial_key_values, &transport).await?;
     let chitchat = chitchat_handle.chitchat();
-    let api = crate::open_api::routes::Api { chitchat: chitchat.clone() };
+    let signing_key = params
+        .keys
+        .wallet
+        .signing_key()
+        .inspect_err(|err| tracing::warn!("Exports can't be signed with the wallet key: {err}"))
+        .ok();
+    let backend_keys = Arc::new(params.backend_keys()?);
+    let revocations = crate::revocations::spawn(
+        chitchat.clone(),
+        params.config.revocations.clone(),
+        backend_keys.clone(),
+    )
+    .await;
+    let verified = VerificationCache::spawn(
+        chitchat.clone(),
+        params.network.timestamp_policy,
+        backend_keys,
+        revocations,
+    )
+    .await;
+    let api = crate::open_api::routes::Api::new(
+        chitchat.clone(),
+        verified.clone(),
+        &params.config,
+        &params.keys.wallet.pubkey,
+    )
+    .with_signing_key(signing_key);
+    let request_metrics = api.request_metrics.clone();
 
     let version = env!("CARGO_PKG_VERSION");
     let description = env!("CARGO_PKG_DESCRIPTION");
@@ -81,7 +151,21 @@ pub async fn run(
     let app = Route::new() //
         .nest("/", api_service)
         .nest("/docs", docs)
-        .with(Cors::new());
+        .with(Cors::new())
+        .around(move |ep, req| {
+            let request_metrics = request_metrics.clone();
+            async move {
+                let started = Instant::now();
+                let res = ep.call(req).await;
+                let (operation, status) = match &res {
+                    Ok(resp) => (resp.data::<OperationId>(), resp.status()),
+                    Err(err) => (err.data::<OperationId>(), err.status()),
+                };
+                let operation = operation.map_or("other", |operation| operation.0);
+                request_metrics.observe(operation, status.as_u16(), started.elapsed());
+                res
+            }
+        });
 
     tracing::info!("Starting REST API server on listen addr {api_addr}");
 
@@ -89,5 +173,5 @@ pub async fn run(
         Server::new(TcpListener::bind(api_addr)).run(app).await.map_err(|err| err.into())
     });
 
-    Ok((chitchat, chitchat_handle, rest_server_handle))
+    Ok((chitchat, chitchat_handle, rest_server_handle, verified))
 }
@@ -0,0 +1,150 @@
+// Keys can be assembled from several sources. Every field is taken from the first source
+// that has it:
+//   1. environment variables IGNITER_WALLET_PUBKEY, IGNITER_WALLET_SECRET, IGNITER_BLS_PUBKEY,
+//      IGNITER_BLS_SECRET and IGNITER_BLS_RND
+//   2. files with the same names in lowercase (`wallet_secret`, ...) in the secrets directory,
+//      `$CREDENTIALS_DIRECTORY` of systemd by default
+//   3. keys document read from an inherited file descriptor (`--keys-fd`)
+//   4. keys document read from `--keys`
+// Documents may be encrypted, see `encrypted_keys`. Only one document is read, the
+// file descriptor wins over `--keys`.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use serde::Serialize;
+
+use crate::config::BlsConfig;
+use crate::config::Keys;
+use crate::config::WalletConfig;
+use crate::encrypted_keys::read_keys;
+
+const ENV_PREFIX: &str = "IGNITER_";
+const FIELDS: [&str; 5] = ["wallet_pubkey", "wallet_secret", "bls_pubkey", "bls_secret", "bls_rnd"];
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KeysSource {
+    pub file: Option<PathBuf>,
+    pub fd: Option<i32>,
+    pub secrets_dir: Option<PathBuf>,
+    // Environment variables are only read for the single identity of the server
+    pub env: bool,
+}
+
+impl KeysSource {
+    pub fn file(path: &Path) -> Self {
+        KeysSource { file: Some(path.to_path_buf()), ..Default::default() }
+    }
+
+    pub fn load(&self) -> anyhow::Result<Keys> {
+        self.load_with(|name| std::env::var(name).ok())
+    }
+
+    fn load_with(&self, env: impl Fn(&str) -> Option<String>) -> anyhow::Result<Keys> {
+        let document = match (self.fd, &self.file) {
+            // Inherited descriptors are visible under /dev/fd on Linux and macOS
+            (Some(fd), _) => {
+                Some((format!("fd {fd}"), read_keys(Path::new(&format!("/dev/fd/{fd}")))?))
+            }
+            (None, Some(path)) => Some((format!("{path:?}"), read_keys(path)?)),
+            (None, None) => None,
+        };
+        let document_fields = document.as_ref().map(|(_, keys)| fields(keys));
+
+        let mut values = vec![];
+        let mut sources = vec![];
+        let mut missing = vec![];
+        for (index, field) in FIELDS.iter().enumerate() {
+            let env_name = format!("{ENV_PREFIX}{}", field.to_uppercase());
+            let from_env =
+                self.env.then(|| env(&env_name)).flatten().map(|value| (value, env_name));
+            let from_dir = || {
+                let path = self.secrets_dir.as_ref()?.join(field);
+                let value = std::fs::read_to_string(&path).ok()?;
+                Some((value.trim_end_matches(['\r', '\n']).to_string(), format!("{path:?}")))
+            };
+            let from_document = || {
+                let (source, _) = document.as_ref()?;
+                let value = document_fields.as_ref()?[index].clone();
+                Some((value, source.clone()))
+            };
+            match from_env.or_else(from_dir).or_else(from_document) {
+                Some((value, source)) => {
+                    values.push(value);
+                    sources.push(format!("{field} from {source}"));
+                }
+                None => missing.push(*field),
+            }
+        }
+        if !missing.is_empty() {
+            bail!("Keys are not complete, missing {}", missing.join(", "));
+        }
+        // Only the origin of every field is reported, never the values
+        eprintln!("Keys: {}", sources.join(", "));
+
+        let [wallet_pubkey, wallet_secret, bls_pubkey, bls_secret, bls_rnd]: [String; 5] =
+            values.try_into().expect("a value for every field");
+        Ok(Keys {
+            wallet: WalletConfig { pubkey: wallet_pubkey, secret: wallet_secret },
+            bls: BlsConfig { pubkey: bls_pubkey, secret: bls_secret, rnd: bls_rnd },
+        })
+    }
+}
+
+fn fields(keys: &Keys) -> [String; 5] {
+    [
+        keys.wallet.pubkey.clone(),
+        keys.wallet.secret.clone(),
+        keys.bls.pubkey.clone(),
+        keys.bls.secret.clone(),
+        keys.bls.rnd.clone(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn fields_follow_the_precedence_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let keys_path = dir.path().join("keys.yaml");
+        let secrets_dir = dir.path().join("credentials");
+        let keys = Keys::generate().unwrap();
+        std::fs::write(&keys_path, serde_yaml::to_string(&keys).unwrap()).unwrap();
+        std::fs::create_dir(&secrets_dir).unwrap();
+        std::fs::write(secrets_dir.join("wallet_secret"), "from_dir\n").unwrap();
+        std::fs::write(secrets_dir.join("bls_secret"), "from_dir").unwrap();
+        let env = HashMap::from([("IGNITER_BLS_SECRET".to_string(), "from_env".to_string())]);
+
+        let source = KeysSource {
+            file: Some(keys_path),
+            secrets_dir: Some(secrets_dir),
+            env: true,
+            ..Default::default()
+        };
+        let loaded = source.load_with(|name| env.get(name).cloned()).unwrap();
+        assert_eq!(loaded.wallet.pubkey, keys.wallet.pubkey);
+        assert_eq!(loaded.wallet.secret, "from_dir");
+        assert_eq!(loaded.bls.secret, "from_env");
+        assert_eq!(loaded.bls.rnd, keys.bls.rnd);
+
+        // Secrets never show up in the debug output, wherever they come from
+        let debug = format!("{loaded:?}");
+        assert!(!debug.contains("from_dir") && !debug.contains("from_env"));
+    }
+
+    #[test]
+    fn missing_fields_are_reported() {
+        let env = HashMap::from([("IGNITER_WALLET_PUBKEY".to_string(), "pubkey".to_string())]);
+        let source = KeysSource { env: true, ..Default::default() };
+        let err = source.load_with(|name| env.get(name).cloned()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Keys are not complete, missing wallet_secret, bls_pubkey, bls_secret, bls_rnd"
+        );
+    }
+}
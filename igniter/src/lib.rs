@@ -1,30 +1,44 @@
 use cli::Params;
+use config::InvalidLicensesPolicy;
 use config::LicenceSignature;
-use config::BACKEND_VERIFYING_KEY;
-pub use config::IGNITER_IMAGE;
+use config::TimestampPolicy;
 use errors::IgniterError;
 use serde::Deserialize;
 use serde::Serialize;
 use strum::Display;
 use strum::EnumString;
-use tvm_types::ed25519_verify;
+pub mod backend_keys;
 pub mod cli;
+pub mod commands;
 mod config;
+pub mod config_reloader;
+pub mod consensus;
+pub mod encrypted_keys;
 pub mod errors;
 pub mod gossip;
+pub mod keys_source;
+pub mod network;
 pub mod open_api;
+pub mod revocations;
 pub mod revoked_license_watcher;
+pub mod seeds;
+mod signature_batch;
+pub mod supervisor;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 pub mod utils;
-use base64::engine::general_purpose::STANDARD;
-use base64::Engine as _;
+pub mod verification_cache;
 
+use crate::backend_keys::BackendKeyring;
 use crate::open_api::routes::check_proxy_socket_addresses;
 use crate::open_api::routes::Licences;
 use crate::open_api::routes::VerifiedNodeStateNoLicenses;
+use crate::signature_batch::SignedMessage;
 
 pub const MAX_ALLOWED_LICENSES: u32 = 20;
 
+const SIGNATURE_CHECKS: usize = 3;
+
 #[derive(Debug, Clone, Copy, Display, EnumString, PartialEq, Serialize, Deserialize)]
 #[strum(serialize_all = "snake_case")]
 pub enum ZerostateKeys {
@@ -34,6 +48,10 @@ pub enum ZerostateKeys {
     Licenses,
     Signatures,
     Version,
+    // SHA-256 of the canonical export as seen by the node, see `consensus`
+    ExportHash,
+    // Signed revocation list of the backend, see `revocations`
+    Revocations,
 }
 
 impl Params {
@@ -52,12 +70,23 @@ impl Params {
         ]
         .to_vec();
 
-        let verified_signatures = VerifiedSignatures::create(
-            &self.config.signatures,
-            &BACKEND_VERIFYING_KEY,
-            &self.keys.wallet.pubkey,
-            &self.keys.bls.pubkey,
-        )?;
+        let backend_keys = self.backend_keys()?;
+        let verified_signatures = match self.config.invalid_licenses {
+            InvalidLicensesPolicy::Reject => VerifiedSignatures::create(
+                &self.config.signatures,
+                &backend_keys,
+                &self.keys.wallet.pubkey,
+                &self.keys.bls.pubkey,
+                &self.timestamp_policy(),
+            )?,
+            InvalidLicensesPolicy::Skip => VerifiedSignatures::create_valid_subset(
+                &self.config.signatures,
+                &backend_keys,
+                &self.keys.wallet.pubkey,
+                &self.keys.bls.pubkey,
+                &self.timestamp_policy(),
+            )?,
+        };
 
         keys.push((
             ZerostateKeys::Signatures.to_string(),
@@ -69,6 +98,20 @@ impl Params {
 
         Ok(keys)
     }
+
+    pub fn backend_keys(&self) -> Result<BackendKeyring, IgniterError> {
+        BackendKeyring::from_config(&self.network, &self.config.backend_keys)
+    }
+
+    // The peers check the own delegations with the policy of the network, `timestamp_policy` of
+    // config.yaml can only make it stricter
+    pub fn timestamp_policy(&self) -> TimestampPolicy {
+        self.network.timestamp_policy.strictest(&self.config.timestamp_policy)
+    }
+
+    pub fn cluster_id(&self) -> &str {
+        self.config.cluster_id.as_deref().unwrap_or(&self.network.cluster_id)
+    }
 }
 
 impl LicenceSignature {
@@ -99,40 +142,51 @@ impl LicenceSignature {
         .into_bytes()
     }
 
-    fn check_license_proof_sig(&self, backend_pk: &[u8; 32]) -> Result<(), IgniterError> {
+    // One message per backend key valid at the delegation timestamp, see `backend_keys`
+    fn license_proof_messages(
+        &self,
+        backend_keys: &BackendKeyring,
+    ) -> Result<Vec<SignedMessage>, IgniterError> {
         let message = Self::license_proof_prepare(&self.license_id, &self.license_owner_pubkey);
-        let signature =
-            STANDARD.decode(&self.license_proof_sig).map_err(|_| IgniterError::LicenseProofSig)?;
-        ed25519_verify(backend_pk, &message, &signature).map_err(|_| IgniterError::LicenseProofSig)
+        let messages: Vec<SignedMessage> = backend_keys
+            .keys_at(self.timestamp)
+            .map(|(_, key)| SignedMessage::new(key, message.clone(), &self.license_proof_sig))
+            .collect::<anyhow::Result<_>>()
+            .map_err(|_| IgniterError::LicenseProofSig)?;
+        if messages.is_empty() {
+            return Err(IgniterError::NoBackendKey { timestamp: self.timestamp });
+        }
+        Ok(messages)
+    }
+
+    // Id of the key the `index`-th license proof message was made for
+    fn backend_key_id(&self, backend_keys: &BackendKeyring, index: usize) -> String {
+        let (key_id, _) = backend_keys.keys_at(self.timestamp).nth(index).expect("key of message");
+        key_id.to_string()
     }
 
     // delegation_sig: check data {license_id, license_owner_pubkey,provider_pubkey, ​​timestamp } with license_owner_pubkey
-    fn check_delegation_sig(&self) -> Result<(), IgniterError> {
-        let check = || -> anyhow::Result<()> {
+    fn delegation_message(&self) -> Result<SignedMessage, IgniterError> {
+        let message = || -> anyhow::Result<SignedMessage> {
             let data = Self::delegation_prepare(
                 &self.license_id,
                 &self.license_owner_pubkey,
                 &self.provider_pubkey,
                 self.timestamp,
             );
-
-            let signature = STANDARD.decode(&self.delegation_sig)?;
             let pub_key = hex::decode(&self.license_owner_pubkey)?;
-            let pub_key: &[u8; 32] = pub_key.as_slice().try_into()?;
-
-            ed25519_verify(pub_key, &data, &signature).map_err(|_| IgniterError::DelegationSig)?;
-            Ok(())
+            SignedMessage::new(&pub_key, data, &self.delegation_sig)
         };
-        check().map_err(|_| IgniterError::DelegationSig)
+        message().map_err(|_| IgniterError::DelegationSig)
     }
 
     // delegation_confirm_sig : check data {license_id,license_owner_pubkey, provider_pubkey } with provider_pubkey
-    fn check_delegation_confirm_sig(
+    fn delegation_confirm_message(
         &self,
         bk_node_owner_pubkey: &str,
         bk_bls_pubkey: &str,
-    ) -> Result<(), IgniterError> {
-        let check = || -> anyhow::Result<()> {
+    ) -> Result<SignedMessage, IgniterError> {
+        let message = || -> anyhow::Result<SignedMessage> {
             let data = Self::delegation_confirm_prepare(
                 &self.license_id,
                 &self.license_owner_pubkey,
@@ -140,88 +194,331 @@ impl LicenceSignature {
                 bk_node_owner_pubkey,
                 bk_bls_pubkey,
             );
-            let signature = STANDARD.decode(&self.delegation_confirm_sig)?;
             let pub_key = hex::decode(&self.provider_pubkey)?;
-            let pub_key: &[u8; 32] = pub_key.as_slice().try_into()?;
-
-            ed25519_verify(pub_key, &data, &signature)
-                .map_err(|_| IgniterError::DelegationConfirmSig)?;
-            Ok(())
+            SignedMessage::new(&pub_key, data, &self.delegation_confirm_sig)
         };
-        check().map_err(|_| IgniterError::DelegationConfirmSig)
+        message().map_err(|_| IgniterError::DelegationConfirmSig)
+    }
+
+    // Messages of the signature checks, in `LicenseCheck::ALL` order. A check passes if any of
+    // its messages is verified.
+    fn signed_messages(
+        &self,
+        backend_keys: &BackendKeyring,
+        bk_node_owner_pubkey: &str,
+        bk_bls_pubkey: &str,
+    ) -> [(LicenseCheck, Result<Vec<SignedMessage>, IgniterError>); SIGNATURE_CHECKS] {
+        [
+            (LicenseCheck::LicenseProofSig, self.license_proof_messages(backend_keys)),
+            (LicenseCheck::DelegationSig, self.delegation_message().map(|m| vec![m])),
+            (
+                LicenseCheck::DelegationConfirmSig,
+                self.delegation_confirm_message(bk_node_owner_pubkey, bk_bls_pubkey)
+                    .map(|m| vec![m]),
+            ),
+        ]
     }
 
+    // Returns the id of the backend key that verified `license_proof_sig`
     pub fn check_signatures(
         &self,
-        backend_pk: &[u8; 32],
+        backend_keys: &BackendKeyring,
         bk_node_owner_pubkey: &str,
         bk_bls_pubkey: &str,
-    ) -> Result<(), IgniterError> {
-        self.check_license_proof_sig(backend_pk)?;
-        self.check_delegation_sig()?;
-        self.check_delegation_confirm_sig(bk_node_owner_pubkey, bk_bls_pubkey)?;
+    ) -> Result<String, IgniterError> {
+        let mut backend_key_id = String::new();
+        for (check, messages) in
+            self.signed_messages(backend_keys, bk_node_owner_pubkey, bk_bls_pubkey)
+        {
+            let Some(index) = messages?.iter().position(SignedMessage::verify) else {
+                return Err(check.failure());
+            };
+            if check == LicenseCheck::LicenseProofSig {
+                backend_key_id = self.backend_key_id(backend_keys, index);
+            }
+        }
+        Ok(backend_key_id)
+    }
+}
+
+impl TimestampPolicy {
+    // `now` is in seconds since the Unix epoch
+    pub fn check(&self, timestamp: u64, now: u64) -> Result<(), IgniterError> {
+        if timestamp > now.saturating_add(self.max_future_secs) {
+            return Err(IgniterError::FutureTimestamp {
+                timestamp,
+                max_future_secs: self.max_future_secs,
+            });
+        }
+        if let Some(min_timestamp) = self.min_timestamp.filter(|min| timestamp < *min) {
+            return Err(IgniterError::StaleTimestamp { timestamp, min_timestamp });
+        }
         Ok(())
     }
+
+    // Rejects what either of the policies rejects
+    pub fn strictest(&self, other: &TimestampPolicy) -> TimestampPolicy {
+        TimestampPolicy {
+            max_future_secs: self.max_future_secs.min(other.max_future_secs),
+            min_timestamp: self.min_timestamp.max(other.min_timestamp),
+        }
+    }
+}
+
+pub fn decode_backend_key(backend_pk: &str) -> Result<[u8; 32], IgniterError> {
+    hex::decode(backend_pk)
+        .map_err(|_| IgniterError::InvalidBackendKey)?
+        .try_into()
+        .map_err(|_| IgniterError::InvalidBackendKey)
+}
+
+#[derive(Debug, Clone, Copy, Display, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+pub enum LicenseCheck {
+    LicenseProofSig,
+    DelegationSig,
+    DelegationConfirmSig,
+    Timestamp,
+    Unique,
+}
+
+impl LicenseCheck {
+    pub const ALL: [LicenseCheck; 5] = [
+        LicenseCheck::LicenseProofSig,
+        LicenseCheck::DelegationSig,
+        LicenseCheck::DelegationConfirmSig,
+        LicenseCheck::Timestamp,
+        LicenseCheck::Unique,
+    ];
+
+    // Error of a signature check whose signature does not match
+    fn failure(self) -> IgniterError {
+        match self {
+            LicenseCheck::LicenseProofSig => IgniterError::LicenseProofSig,
+            LicenseCheck::DelegationSig => IgniterError::DelegationSig,
+            LicenseCheck::DelegationConfirmSig => IgniterError::DelegationConfirmSig,
+            LicenseCheck::Timestamp | LicenseCheck::Unique => {
+                unreachable!("{self} is not a signature check")
+            }
+        }
+    }
+}
+
+// Results of all checks of a single `signatures` entry
+#[derive(Debug)]
+pub struct LicenseReport {
+    pub license_id: String,
+    // Backend key that verified `license_proof_sig`
+    pub backend_key_id: Option<String>,
+    // One result per `LicenseCheck::ALL` entry, in the same order
+    pub checks: [Result<(), IgniterError>; 5],
+}
+
+impl LicenseReport {
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(Result::is_ok)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = (LicenseCheck, &IgniterError)> {
+        LicenseCheck::ALL
+            .into_iter()
+            .zip(&self.checks)
+            .filter_map(|(check, result)| result.as_ref().err().map(|err| (check, err)))
+    }
+
+    pub fn reason(&self) -> String {
+        self.failures().map(|(_, err)| err.to_string()).collect::<Vec<_>>().join(", ")
+    }
+}
+
+pub fn check_license_count(count: usize) -> Result<(), IgniterError> {
+    if count > MAX_ALLOWED_LICENSES as usize {
+        return Err(IgniterError::TooManyLicenses);
+    }
+    if count == 0 {
+        return Err(IgniterError::NoLicenses);
+    }
+    Ok(())
 }
 
 pub struct VerifiedSignatures {
     inner: Vec<LicenceSignature>,
+    // License id to the id of the backend key that verified its proof
+    backend_key_ids: BTreeMap<String, String>,
 }
 
 impl VerifiedSignatures {
     pub fn from_checked_state(state: &VerifiedNodeStateNoLicenses) -> Self {
-        VerifiedSignatures { inner: state.get_signatures() }
+        VerifiedSignatures {
+            inner: state.get_signatures(),
+            backend_key_ids: state.get_backend_key_ids(),
+        }
+    }
+
+    // Signatures of the own gossip state, they were verified before they were published
+    pub(crate) fn from_published(signatures: Vec<LicenceSignature>) -> Self {
+        VerifiedSignatures { inner: signatures, backend_key_ids: BTreeMap::new() }
     }
 
     pub fn get(&self) -> &Vec<LicenceSignature> {
         &self.inner
     }
 
+    pub fn backend_key_ids(&self) -> &BTreeMap<String, String> {
+        &self.backend_key_ids
+    }
+
+    // Runs every check on every entry instead of stopping at the first failure. The signatures
+    // of all entries are verified together, see `signature_batch`.
+    pub fn validate(
+        signatures: &[LicenceSignature],
+        backend_keys: &BackendKeyring,
+        bk_node_owner_pubkey: &str,
+        bk_bls_pubkey: &str,
+        timestamps: &TimestampPolicy,
+    ) -> Result<Vec<LicenseReport>, IgniterError> {
+        let now = telemetry_utils::now_ms() / 1000;
+
+        // Entries whose keys or signatures can't be decoded fail without verification
+        let mut messages = vec![];
+        let mut decoded = vec![];
+        for sig in signatures {
+            let checks = sig.signed_messages(backend_keys, bk_node_owner_pubkey, bk_bls_pubkey);
+            decoded.push(checks.map(|(check, candidates)| {
+                let count = candidates.map(|candidates| {
+                    let count = candidates.len();
+                    messages.extend(candidates);
+                    count
+                });
+                (check, count)
+            }));
+        }
+        let mut verified = signature_batch::verify_all(&messages).into_iter();
+
+        let mut seen = HashSet::new();
+        let reports = signatures
+            .iter()
+            .zip(decoded)
+            .map(|(sig, decoded)| {
+                let [license_proof_sig, delegation_sig, delegation_confirm_sig] =
+                    decoded.map(|(check, count)| {
+                        let results: Vec<bool> = verified.by_ref().take(count?).collect();
+                        results.into_iter().position(|valid| valid).ok_or_else(|| check.failure())
+                    });
+                let backend_key_id = license_proof_sig
+                    .as_ref()
+                    .ok()
+                    .map(|index| sig.backend_key_id(backend_keys, *index));
+                let timestamp = timestamps.check(sig.timestamp, now);
+                let unique = if seen.insert(sig.license_id.clone()) {
+                    Ok(())
+                } else {
+                    Err(IgniterError::DuplicateLicenseId(sig.license_id.to_string()))
+                };
+                LicenseReport {
+                    license_id: sig.license_id.clone(),
+                    backend_key_id,
+                    checks: [
+                        license_proof_sig.map(drop),
+                        delegation_sig.map(drop),
+                        delegation_confirm_sig.map(drop),
+                        timestamp,
+                        unique,
+                    ],
+                }
+            })
+            .collect();
+        Ok(reports)
+    }
+
     pub fn create(
-        signatures: &Vec<LicenceSignature>,
-        backend_pk: &str,
+        signatures: &[LicenceSignature],
+        backend_keys: &BackendKeyring,
         bk_node_owner_pubkey: &str,
         bk_bls_pubkey: &str,
+        timestamps: &TimestampPolicy,
     ) -> Result<VerifiedSignatures, IgniterError> {
-        if signatures.len() > MAX_ALLOWED_LICENSES as usize {
-            return Err(IgniterError::TooManyLicenses);
-        }
-        if signatures.is_empty() {
-            return Err(IgniterError::NoLicenses);
+        check_license_count(signatures.len())?;
+
+        let reports = Self::validate(
+            signatures,
+            backend_keys,
+            bk_node_owner_pubkey,
+            bk_bls_pubkey,
+            timestamps,
+        )?;
+        let mut backend_key_ids = BTreeMap::new();
+        for report in reports {
+            if let Some(err) = report.checks.into_iter().find_map(Result::err) {
+                return Err(err);
+            }
+            backend_key_ids.extend(report.backend_key_id.map(|id| (report.license_id, id)));
         }
-        let backend_pubkey_bytes =
-            hex::decode(backend_pk).map_err(|_| IgniterError::InvalidBackendKey)?;
+        Ok(VerifiedSignatures { inner: signatures.to_vec(), backend_key_ids })
+    }
 
-        let backend_pubkey_slice: &[u8; 32] = backend_pubkey_bytes
-            .as_slice()
-            .try_into()
-            .map_err(|_| IgniterError::InvalidBackendKey)?;
+    // Keeps only the entries that pass all checks, each rejected entry is reported with a warning
+    pub fn create_valid_subset(
+        signatures: &[LicenceSignature],
+        backend_keys: &BackendKeyring,
+        bk_node_owner_pubkey: &str,
+        bk_bls_pubkey: &str,
+        timestamps: &TimestampPolicy,
+    ) -> Result<VerifiedSignatures, IgniterError> {
+        let reports = Self::validate(
+            signatures,
+            backend_keys,
+            bk_node_owner_pubkey,
+            bk_bls_pubkey,
+            timestamps,
+        )?;
 
-        // Check all signatures and check that all are unique
-        let mut seen = HashSet::new();
-        for sig in signatures {
-            sig.check_signatures(backend_pubkey_slice, bk_node_owner_pubkey, bk_bls_pubkey)?;
-            if !seen.insert(sig.license_id.clone()) {
-                return Err(IgniterError::DuplicateLicenseId(sig.license_id.to_string()));
+        let mut valid = vec![];
+        let mut backend_key_ids = BTreeMap::new();
+        for (sig, report) in signatures.iter().zip(reports) {
+            if report.is_ok() {
+                valid.push(sig.clone());
+                backend_key_ids.extend(report.backend_key_id.map(|id| (report.license_id, id)));
+            } else {
+                tracing::warn!("License {} is skipped: {}", report.license_id, report.reason());
             }
         }
-        Ok(VerifiedSignatures { inner: signatures.clone() })
+        if valid.len() < signatures.len() {
+            tracing::warn!(
+                "{} of {} licenses are invalid and will not be gossiped",
+                signatures.len() - valid.len(),
+                signatures.len()
+            );
+        }
+        check_license_count(valid.len())?;
+        Ok(VerifiedSignatures { inner: valid, backend_key_ids })
     }
 }
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine as _;
     use ed25519_dalek::SigningKey;
     use rand::rngs::OsRng;
     use serde_json::json;
     use tvm_types::ed25519_sign_with_secret;
 
     use super::*;
+    use crate::config::BackendKey;
     use crate::config::BlsConfig;
     use crate::config::Config;
     use crate::config::Keys;
+    use crate::config::LicenseWatcherConfig;
+    use crate::config::RevocationsConfig;
     use crate::config::WalletConfig;
+    use crate::network::NetworkProfile;
+
+    fn keyring(backend_signing_key: &SigningKey) -> BackendKeyring {
+        let backend_pk = hex::encode(backend_signing_key.verifying_key().to_bytes());
+        BackendKeyring::new(vec![BackendKey::new("test", &backend_pk)]).unwrap()
+    }
 
     fn reveal_keypair(signing_key: &SigningKey) -> (String, String) {
         let secret_verifying_key_pair = hex::encode(signing_key.to_keypair_bytes());
@@ -299,9 +596,9 @@ mod tests {
             &create_license_signature(&backend_signing_key.to_bytes(), 1, pubkey, bls_pubkey)[0];
 
         let another_backend_signing_key: SigningKey = SigningKey::generate(&mut csprng);
-        let verifying_key = &another_backend_signing_key.verifying_key().to_bytes();
+        let backend_keys = keyring(&another_backend_signing_key);
 
-        assert!(lic_sig.check_signatures(verifying_key, pubkey, bls_pubkey).is_err());
+        assert!(lic_sig.check_signatures(&backend_keys, pubkey, bls_pubkey).is_err());
     }
 
     #[test]
@@ -312,9 +609,127 @@ mod tests {
         let bls_pubkey="8cf7d141cade81a44c8bc58a02b0448e85e77d47d9c644adfe3512d3c5fcdc2a028cfb96aff704a70f2cce27c96cd706";
         let lic_sig =
             &create_license_signature(&backend_signing_key.to_bytes(), 1, pubkey, bls_pubkey)[0];
-        let verifying_key = &backend_signing_key.verifying_key().to_bytes();
+        let backend_keys = keyring(&backend_signing_key);
+
+        assert_eq!(lic_sig.check_signatures(&backend_keys, pubkey, bls_pubkey).unwrap(), "test");
+    }
+
+    #[test]
+    fn test_validate_reports_every_license() {
+        let backend_signing_key: SigningKey = SigningKey::generate(&mut OsRng);
+        let backend_keys = keyring(&backend_signing_key);
+        let pubkey = "3ef72c59a33ba75a484cfb126bd9e55db267cbd944110374d0b78a9e474c6c87";
+        let bls_pubkey="8cf7d141cade81a44c8bc58a02b0448e85e77d47d9c644adfe3512d3c5fcdc2a028cfb96aff704a70f2cce27c96cd706";
+        let mut signatures =
+            create_license_signature(&backend_signing_key.to_bytes(), 3, pubkey, bls_pubkey);
+        signatures[1].delegation_sig = signatures[0].delegation_sig.clone();
+        signatures.push(signatures[2].clone());
+        let policy = TimestampPolicy::default();
+
+        let reports =
+            VerifiedSignatures::validate(&signatures, &backend_keys, pubkey, bls_pubkey, &policy)
+                .unwrap();
+        let failures: Vec<Vec<LicenseCheck>> =
+            reports.iter().map(|r| r.failures().map(|(check, _)| check).collect()).collect();
+        assert_eq!(
+            failures,
+            vec![vec![], vec![LicenseCheck::DelegationSig], vec![], vec![LicenseCheck::Unique]]
+        );
 
-        assert!(lic_sig.check_signatures(verifying_key, pubkey, bls_pubkey).is_ok());
+        assert!(matches!(
+            VerifiedSignatures::create(&signatures, &backend_keys, pubkey, bls_pubkey, &policy),
+            Err(IgniterError::DelegationSig)
+        ));
+
+        let valid = VerifiedSignatures::create_valid_subset(
+            &signatures,
+            &backend_keys,
+            pubkey,
+            bls_pubkey,
+            &policy,
+        )
+        .unwrap();
+        let valid_ids: Vec<&str> = valid.get().iter().map(|s| s.license_id.as_str()).collect();
+        assert_eq!(valid_ids, vec!["license_id_0", "license_id_2"]);
+    }
+
+    #[test]
+    fn test_license_proof_is_checked_with_the_keys_of_its_timestamp() {
+        let old_key = SigningKey::generate(&mut OsRng);
+        let new_key = SigningKey::generate(&mut OsRng);
+        let pubkey = "3ef72c59a33ba75a484cfb126bd9e55db267cbd944110374d0b78a9e474c6c87";
+        let bls_pubkey="8cf7d141cade81a44c8bc58a02b0448e85e77d47d9c644adfe3512d3c5fcdc2a028cfb96aff704a70f2cce27c96cd706";
+        // Delegated at 1234567890 with a proof of the old key
+        let signatures = create_license_signature(&old_key.to_bytes(), 1, pubkey, bls_pubkey);
+        let key = |key_id: &str, signing_key: &SigningKey| {
+            BackendKey::new(key_id, &hex::encode(signing_key.verifying_key().to_bytes()))
+        };
+        let validate = |keys: Vec<BackendKey>| {
+            let backend_keys = BackendKeyring::new(keys).unwrap();
+            let policy = TimestampPolicy::default();
+            VerifiedSignatures::validate(&signatures, &backend_keys, pubkey, bls_pubkey, &policy)
+                .unwrap()
+                .remove(0)
+        };
+
+        // Both keys cover the timestamp, the one that matches is reported
+        let report = validate(vec![
+            BackendKey { valid_from: Some(1234567000), ..key("new", &new_key) },
+            BackendKey { valid_until: Some(1234568000), ..key("old", &old_key) },
+        ]);
+        assert!(report.is_ok());
+        assert_eq!(report.backend_key_id.as_deref(), Some("old"));
+
+        // The old key is retired before the delegation
+        let report = validate(vec![
+            BackendKey { valid_from: Some(1234567000), ..key("new", &new_key) },
+            BackendKey { valid_until: Some(1234567000), ..key("old", &old_key) },
+        ]);
+        assert!(matches!(report.checks[0], Err(IgniterError::LicenseProofSig)));
+        let report =
+            validate(vec![BackendKey { valid_from: Some(1234568000), ..key("new", &new_key) }]);
+        assert!(matches!(report.checks[0], Err(IgniterError::NoBackendKey { .. })));
+    }
+
+    #[test]
+    fn test_timestamp_policy() {
+        let policy = TimestampPolicy { max_future_secs: 60, min_timestamp: Some(1000) };
+        assert!(policy.check(1000, 2000).is_ok());
+        assert!(policy.check(2060, 2000).is_ok());
+        assert!(matches!(
+            policy.check(2061, 2000),
+            Err(IgniterError::FutureTimestamp { timestamp: 2061, max_future_secs: 60 })
+        ));
+        assert!(matches!(
+            policy.check(999, 2000),
+            Err(IgniterError::StaleTimestamp { timestamp: 999, min_timestamp: 1000 })
+        ));
+
+        let network = TimestampPolicy { max_future_secs: 600, min_timestamp: Some(500) };
+        let strictest = network.strictest(&policy);
+        assert_eq!(strictest, TimestampPolicy { max_future_secs: 60, min_timestamp: Some(1000) });
+        assert_eq!(network.strictest(&TimestampPolicy::default()), network);
+    }
+
+    #[test]
+    fn test_valid_subset_can_not_be_empty() {
+        let backend_signing_key: SigningKey = SigningKey::generate(&mut OsRng);
+        let other_backend_keys = keyring(&SigningKey::generate(&mut OsRng));
+        let pubkey = "3ef72c59a33ba75a484cfb126bd9e55db267cbd944110374d0b78a9e474c6c87";
+        let bls_pubkey="8cf7d141cade81a44c8bc58a02b0448e85e77d47d9c644adfe3512d3c5fcdc2a028cfb96aff704a70f2cce27c96cd706";
+        let signatures =
+            create_license_signature(&backend_signing_key.to_bytes(), 2, pubkey, bls_pubkey);
+
+        assert!(matches!(
+            VerifiedSignatures::create_valid_subset(
+                &signatures,
+                &other_backend_keys,
+                pubkey,
+                bls_pubkey,
+                &TimestampPolicy::default()
+            ),
+            Err(IgniterError::NoLicenses)
+        ));
     }
 
     #[test]
@@ -348,7 +763,8 @@ mod tests {
                 license_proof_sig: "license_proof_sig".to_string(),
             },
         ];
-        let checked_licences = VerifiedSignatures { inner: signatures.to_vec() };
+        let checked_licences =
+            VerifiedSignatures { inner: signatures.to_vec(), backend_key_ids: BTreeMap::new() };
 
         let licences = Licences::derive_licences(&checked_licences);
         assert_eq!(licences.get().get("owner_pubkey_1").unwrap(), &2);
@@ -382,15 +798,27 @@ mod tests {
     fn default_config_and_keys() -> (Config, Keys) {
         (
             Config {
-                cluster_id: "test_cluster".to_string(),
+                network: None,
+                networks_file: None,
+                cluster_id: Some("test_cluster".to_string()),
                 proxies: vec![],
                 listen_addr: "127.0.0.1:10000".parse().expect("Invalid SocketAddr format"),
                 api_addr: "127.0.0.1:10000".parse().expect("Invalid SocketAddr format"),
                 advertise_addr: "127.0.0.1:10000".parse().expect("Invalid SocketAddr format"),
                 seeds: vec![],
+                seeds_file: None,
+                seeds_mirrors: vec![],
+                seeds_cache: None,
+                seeds_verifying_key: None,
                 node_id: None,
                 interval: 5,
                 signatures: vec![create_test_signature()],
+                invalid_licenses: InvalidLicensesPolicy::Reject,
+                timestamp_policy: TimestampPolicy::default(),
+                backend_keys: vec![],
+                revocations: RevocationsConfig::default(),
+                license_watcher: LicenseWatcherConfig::default(),
+                min_live_nodes: 2,
                 auto_update: false,
             },
             Keys {
@@ -417,18 +845,28 @@ mod tests {
 
     #[test]
     fn test_to_gossip_kv_no_signatures() {
-        std::env::set_var("DEV_MODE", "true");
         let (config, keys) = default_config_and_keys();
-        let params = Params { config, keys, docker_socket: None, docker_config: None };
+        let params = Params {
+            config,
+            network: NetworkProfile::devnet(),
+            keys,
+            docker_socket: None,
+            docker_config: None,
+        };
         let result = params.to_gossip().unwrap();
         assert_eq!(result.len(), 6);
     }
     #[test]
     fn test_to_gossip_kv_one_signature() {
-        std::env::set_var("DEV_MODE", "true");
         let (mut config, keys) = default_config_and_keys();
         config.signatures = vec![create_test_signature()];
-        let params = Params { config, keys, docker_socket: None, docker_config: None };
+        let params = Params {
+            config,
+            network: NetworkProfile::devnet(),
+            keys,
+            docker_socket: None,
+            docker_config: None,
+        };
         let result = params.to_gossip().unwrap();
         assert_eq!(result.len(), 6);
         let hashmap: HashMap<String, String> = result.into_iter().collect();
@@ -456,7 +894,13 @@ mod tests {
         };
         // the same signature twice
         config.signatures = vec![signature_0.clone(), signature_1.clone(), signature_0.clone()];
-        let params = Params { config, keys, docker_socket: None, docker_config: None };
+        let params = Params {
+            config,
+            network: NetworkProfile::devnet(),
+            keys,
+            docker_socket: None,
+            docker_config: None,
+        };
         let result = params.to_gossip().unwrap();
         assert_eq!(result.len(), 7);
 
@@ -470,7 +914,6 @@ mod tests {
 
     #[test]
     fn test_back_and_front_use_the_same_algorithm() {
-        std::env::set_var("DEV_MODE", "true");
         let lic = LicenceSignature {
             license_id: "2aebf602-7503-4572-976c-79f206f9b2c0".to_string(),
             license_proof_sig: "4tvVKDRZPKOkV+bqTjUSEuNPP4zYio7kodo+UylCzvFCKEYUhGjF4VF5JbGzU/s2l98V31lMvBHKPv1yvw6dDg==".to_string(),
@@ -480,13 +923,11 @@ mod tests {
             delegation_confirm_sig: "delegation_confirm_sig".to_string(),
             timestamp: 1736944335,
         };
-        let backend_verifying_key =
-            hex::decode(BACKEND_VERIFYING_KEY.as_bytes()).expect("Can't decode hex");
-
-        let backend_verifying_key: &[u8; 32] =
-            backend_verifying_key.as_slice().try_into().expect("Vec must have exactly 32 elements");
+        let backend_keys = BackendKeyring::from_config(&NetworkProfile::devnet(), &[]).unwrap();
 
-        assert!(lic.check_license_proof_sig(backend_verifying_key).is_ok());
-        assert!(lic.check_delegation_sig().is_ok());
+        let messages = lic.license_proof_messages(&backend_keys).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].verify());
+        assert!(lic.delegation_message().unwrap().verify());
     }
 }
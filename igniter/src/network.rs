@@ -0,0 +1,234 @@
+// Network profiles bundle the settings that differ between the Acki Nacki networks: backend
+// keys, the igniter image for auto update, the seeds sources with their signing key, the gossip
+// cluster id and the timestamp policy for the gossip. `mainnet`, `devnet` and `local` are built
+// in, custom profiles are read from a networks file. The profile is selected with `--network` or
+// `network` in config.yaml.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::cli::NetworkArgs;
+use crate::config::read_yaml;
+use crate::config::BackendKey;
+use crate::config::Config;
+use crate::config::TimestampPolicy;
+
+pub const MAINNET: &str = "mainnet";
+pub const DEVNET: &str = "devnet";
+pub const LOCAL: &str = "local";
+
+const DEFAULT_CLUSTER_ID: &str = env!("CARGO_PKG_NAME");
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkProfile {
+    // Taken from the key of the networks file
+    #[serde(skip_deserializing)]
+    pub name: String,
+
+    // Keys license proofs and revocation lists are checked with, `backend_keys` of config.yaml
+    // replace them
+    #[serde(default)]
+    pub backend_keys: Vec<BackendKey>,
+
+    // Image the container updater follows, the network has no auto update without it
+    #[serde(default)]
+    pub image: Option<String>,
+
+    // Tried after `seeds` and `seeds_file` of config.yaml
+    #[serde(default)]
+    pub seeds_url: Option<String>,
+
+    // Tried after `seeds_url` and `seeds_mirrors` of config.yaml
+    #[serde(default)]
+    pub seeds_mirrors: Vec<String>,
+
+    // Hex encoded key of the seeds authority, if set downloaded and cached seeds lists must be
+    // signed with it. `seeds_verifying_key` of config.yaml replaces it.
+    #[serde(default)]
+    pub seeds_verifying_key: Option<String>,
+
+    // `seeds_url` can't be downloaded without GITHUB_TOKEN
+    #[serde(default)]
+    pub github_token_required: bool,
+
+    // `cluster_id` of config.yaml takes precedence
+    #[serde(default = "default_cluster_id")]
+    pub cluster_id: String,
+
+    // Applied to the delegations of all nodes, so every igniter of the network accepts the same
+    // node states. `timestamp_policy` of config.yaml only restricts the own delegations.
+    #[serde(default)]
+    pub timestamp_policy: TimestampPolicy,
+}
+
+impl NetworkProfile {
+    pub fn mainnet() -> Self {
+        NetworkProfile {
+            name: MAINNET.to_string(),
+            backend_keys: vec![BackendKey::new(
+                "main",
+                "75631f108a226740a8649ff7946bf19d2884c373615d27f5b6d1863b5d97adf3",
+            )],
+            image: Some("teamgosh/acki-nacki-igniter".to_string()),
+            seeds_url: Some(
+                "https://raw.githubusercontent.com/ackinacki/acki-nacki-igniter-seeds/refs/heads/main/seeds.yaml"
+                    .to_string(),
+            ),
+            seeds_mirrors: vec![],
+            seeds_verifying_key: None,
+            github_token_required: false,
+            cluster_id: default_cluster_id(),
+            timestamp_policy: TimestampPolicy::default(),
+        }
+    }
+
+    pub fn devnet() -> Self {
+        NetworkProfile {
+            name: DEVNET.to_string(),
+            backend_keys: vec![BackendKey::new(
+                "dev",
+                "ee99af158c8b50f6bee3360615e08db957bd898568638f308d8f320cf4e37638",
+            )],
+            image: Some("docker.gosh.sh/acki-nacki-igniter-pre-release".to_string()),
+            seeds_url: Some(
+                "https://raw.githubusercontent.com/gosh-sh/acki-nacki-igniter-seeds/refs/heads/main/seeds.yaml"
+                    .to_string(),
+            ),
+            seeds_mirrors: vec![],
+            seeds_verifying_key: None,
+            github_token_required: true,
+            cluster_id: default_cluster_id(),
+            timestamp_policy: TimestampPolicy::default(),
+        }
+    }
+
+    // Isolated network, e.g. for integration tests: the backend keys and seeds come from
+    // config.yaml and the instances never auto update
+    pub fn local() -> Self {
+        NetworkProfile {
+            name: LOCAL.to_string(),
+            backend_keys: vec![],
+            image: None,
+            seeds_url: None,
+            seeds_mirrors: vec![],
+            seeds_verifying_key: None,
+            github_token_required: false,
+            cluster_id: format!("{DEFAULT_CLUSTER_ID}-{LOCAL}"),
+            timestamp_policy: TimestampPolicy::default(),
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            MAINNET => Some(Self::mainnet()),
+            DEVNET => Some(Self::devnet()),
+            LOCAL => Some(Self::local()),
+            _ => None,
+        }
+    }
+
+    // `--network` and `--networks-file` take precedence over config.yaml. Without a network
+    // DEV_MODE=true selects devnet as before the profiles, otherwise mainnet is used.
+    pub fn select(args: &NetworkArgs, config: Option<&Config>) -> anyhow::Result<Self> {
+        let name = args.network.as_deref().or(config.and_then(|config| config.network.as_deref()));
+        let networks_file = args
+            .networks_file
+            .as_deref()
+            .or(config.and_then(|config| config.networks_file.as_deref()));
+        let name = match name {
+            Some(name) => name,
+            None if dev_mode() => {
+                eprintln!("WARNING: DEV_MODE is deprecated, use `--network {DEVNET}` instead");
+                DEVNET
+            }
+            None => MAINNET,
+        };
+        if let Some(network) = Self::builtin(name) {
+            return Ok(network);
+        }
+        let Some(path) = networks_file else {
+            bail!("Unknown network {name}, custom networks require a networks file");
+        };
+        read_networks(path)?
+            .remove(name)
+            .ok_or_else(|| anyhow!("Network {name} is not defined in {path:?}"))
+    }
+}
+
+// Custom profiles by name, the built-in names can't be redefined
+pub fn read_networks(path: &Path) -> anyhow::Result<BTreeMap<String, NetworkProfile>> {
+    let mut networks = read_yaml::<BTreeMap<String, NetworkProfile>>(path)
+        .map_err(|err| anyhow!("Can't parse networks file {path:?}: {err}"))?;
+    for (name, network) in &mut networks {
+        if NetworkProfile::builtin(name).is_some() {
+            bail!("Network {name} is built in and can't be redefined in {path:?}");
+        }
+        network.name = name.clone();
+    }
+    Ok(networks)
+}
+
+fn dev_mode() -> bool {
+    std::env::var("DEV_MODE").is_ok_and(|val| val.eq_ignore_ascii_case("true"))
+}
+
+fn default_cluster_id() -> String {
+    DEFAULT_CLUSTER_ID.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETWORKS: &str = r#"
+it:
+  backend_keys:
+    - key_id: it
+      pubkey: f3d50b12650a49d9a5de34a4022843efc9fc9ba120a038f04d50db310f78f147
+  seeds_url: http://127.0.0.1:8080/seeds.yaml
+  cluster_id: integration-tests
+  timestamp_policy:
+    min_timestamp: 1735689600
+"#;
+
+    fn args(network: Option<&str>, networks_file: Option<&Path>) -> NetworkArgs {
+        NetworkArgs {
+            network: network.map(str::to_string),
+            networks_file: networks_file.map(Path::to_path_buf),
+        }
+    }
+
+    #[test]
+    fn network_is_selected_by_flag_then_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("networks.yaml");
+        std::fs::write(&path, NETWORKS).unwrap();
+        let mut config = read_yaml::<Config>("./tests/config.yaml").unwrap();
+        config.network = Some(LOCAL.to_string());
+
+        let network = NetworkProfile::select(&args(None, None), Some(&config)).unwrap();
+        assert_eq!(network, NetworkProfile::local());
+        let network = NetworkProfile::select(&args(Some(DEVNET), None), Some(&config)).unwrap();
+        assert_eq!(network, NetworkProfile::devnet());
+
+        config.network = Some("it".to_string());
+        assert!(NetworkProfile::select(&args(None, None), Some(&config)).is_err());
+        config.networks_file = Some(path.clone());
+        let network = NetworkProfile::select(&args(None, None), Some(&config)).unwrap();
+        assert_eq!(network.name, "it");
+        assert_eq!(network.cluster_id, "integration-tests");
+        assert_eq!(network.backend_keys[0].key_id, "it");
+        assert_eq!(network.image, None);
+        assert_eq!(network.timestamp_policy.min_timestamp, Some(1735689600));
+        assert_eq!(network.timestamp_policy.max_future_secs, 600);
+        assert!(NetworkProfile::select(&args(Some("other"), Some(&path)), None).is_err());
+
+        std::fs::write(&path, NETWORKS.replace("it:", "mainnet:")).unwrap();
+        assert!(read_networks(&path).is_err());
+    }
+}
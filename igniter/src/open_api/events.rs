@@ -0,0 +1,358 @@
+// Change feed of the cluster for the `/events` endpoint. A single task compares the live nodes
+// on every change of the verified view and publishes typed events, so the clients don't have to
+// poll and re-verify the whole cluster. Every event gets the next sequence number and the last
+// EVENT_HISTORY events are kept, a client that reconnects with `Last-Event-ID` gets the missed
+// ones. The sequence restarts with the process, so the id is `<epoch>-<sequence>` where the
+// epoch is the start time of the feed. An id of another epoch gets the whole history.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use chitchat::ChitchatId;
+use poem_openapi::Enum;
+use poem_openapi::Object;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::Stream;
+use tokio_stream::StreamExt;
+
+use crate::open_api::routes::NodeId;
+use crate::open_api::routes::NodeRejection;
+use crate::utils::RevokedLicense;
+use crate::verification_cache::VerificationCache;
+use crate::verification_cache::VerifiedView;
+
+const EVENT_HISTORY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Enum, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ClusterEventKind {
+    NodeJoined,
+    NodeLeft,
+    NodeStateChanged,
+    LicenseRevoked,
+    NodeRejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct ClusterEvent {
+    // Start time of the feed in milliseconds, with `sequence` it forms the event id
+    pub epoch: u64,
+    // Grows by one with every event, starts from 1 when the process starts
+    pub sequence: u64,
+    pub kind: ClusterEventKind,
+    pub node: Option<NodeId>,
+    // Keys that were set, changed or removed, for `node_state_changed`
+    pub changed_keys: Vec<String>,
+    pub revoked_license: Option<RevokedLicense>,
+    pub rejection: Option<NodeRejection>,
+}
+
+impl ClusterEvent {
+    fn new(kind: ClusterEventKind, node: Option<&ChitchatId>) -> Self {
+        ClusterEvent {
+            epoch: 0,
+            sequence: 0,
+            kind,
+            node: node.map(NodeId::from),
+            changed_keys: vec![],
+            revoked_license: None,
+            rejection: None,
+        }
+    }
+
+    pub fn id(&self) -> String {
+        format!("{}-{}", self.epoch, self.sequence)
+    }
+}
+
+pub type ClusterEvents = Pin<Box<dyn Stream<Item = ClusterEvent> + Send>>;
+
+#[derive(Default)]
+struct History {
+    epoch: u64,
+    last_sequence: u64,
+    events: VecDeque<ClusterEvent>,
+}
+
+pub struct EventFeed {
+    sender: broadcast::Sender<ClusterEvent>,
+    history: Arc<Mutex<History>>,
+    task: JoinHandle<()>,
+}
+
+impl EventFeed {
+    pub fn spawn(verified: VerificationCache) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_HISTORY);
+        let history = Arc::new(Mutex::new(History {
+            epoch: telemetry_utils::now_ms(),
+            ..Default::default()
+        }));
+        let task =
+            tokio::spawn(track_cluster(verified.subscribe(), sender.clone(), history.clone()));
+        EventFeed { sender, history, task }
+    }
+
+    // Events after `last_event_id` followed by the new ones. The stream ends if the client
+    // falls behind by more than EVENT_HISTORY events, it can resume from the last one received.
+    pub fn subscribe(&self, last_event_id: Option<&str>) -> ClusterEvents {
+        // The lock is held by the publisher too, no event is missed or repeated in between
+        let history = self.history.lock().expect("event history lock");
+        let receiver = self.sender.subscribe();
+        // An id of another epoch was issued by a previous process, everything is new for it
+        let after = last_event_id
+            .and_then(parse_event_id)
+            .filter(|(epoch, sequence)| {
+                *epoch == history.epoch && *sequence <= history.last_sequence
+            })
+            .map(|(_, sequence)| sequence);
+        let backlog: Vec<ClusterEvent> = match after {
+            Some(after) => history.events.iter().filter(|e| e.sequence > after).cloned().collect(),
+            None if last_event_id.is_some() => history.events.iter().cloned().collect(),
+            None => vec![],
+        };
+        Box::pin(
+            tokio_stream::iter(backlog).chain(
+                BroadcastStream::new(receiver)
+                    .take_while(|event| event.is_ok())
+                    .filter_map(|event| event.ok()),
+            ),
+        )
+    }
+}
+
+// `<epoch>-<sequence>`
+fn parse_event_id(id: &str) -> Option<(u64, u64)> {
+    let (epoch, sequence) = id.split_once('-')?;
+    Some((epoch.parse().ok()?, sequence.parse().ok()?))
+}
+
+impl Drop for EventFeed {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn track_cluster(
+    mut watcher: watch::Receiver<Arc<VerifiedView>>,
+    sender: broadcast::Sender<ClusterEvent>,
+    history: Arc<Mutex<History>>,
+) {
+    let mut tracker = ClusterTracker::default();
+    loop {
+        let view = watcher.borrow_and_update().clone();
+        let events = tracker.update(&view);
+
+        publish(events, &sender, &history);
+
+        if watcher.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+fn publish(
+    events: Vec<ClusterEvent>,
+    sender: &broadcast::Sender<ClusterEvent>,
+    history: &Mutex<History>,
+) {
+    let mut history = history.lock().expect("event history lock");
+    for mut event in events {
+        history.last_sequence += 1;
+        event.epoch = history.epoch;
+        event.sequence = history.last_sequence;
+        if history.events.len() == EVENT_HISTORY {
+            history.events.pop_front();
+        }
+        history.events.push_back(event.clone());
+        // Nobody listens, the event stays in the history
+        let _ = sender.send(event);
+    }
+}
+
+// What the clients have been told about the cluster so far
+#[derive(Default)]
+struct ClusterTracker {
+    nodes: BTreeMap<ChitchatId, BTreeMap<String, String>>,
+    rejections: HashMap<ChitchatId, NodeRejection>,
+    // Revoked licenses of the last view, a license that is revoked again is reported again
+    revoked: BTreeSet<(String, String)>,
+}
+
+impl ClusterTracker {
+    fn update(&mut self, view: &VerifiedView) -> Vec<ClusterEvent> {
+        let live_nodes = &view.live_nodes;
+        let mut events = vec![];
+        for id in self.nodes.keys().filter(|id| !live_nodes.contains_key(*id)) {
+            events.push(ClusterEvent::new(ClusterEventKind::NodeLeft, Some(id)));
+            self.rejections.remove(id);
+        }
+        self.nodes.retain(|id, _| live_nodes.contains_key(id));
+
+        for (id, node_state) in live_nodes {
+            let key_values: BTreeMap<String, String> =
+                node_state.key_values().map(|(k, v)| (k.into(), v.into())).collect();
+            match self.nodes.get(id) {
+                None => events.push(ClusterEvent::new(ClusterEventKind::NodeJoined, Some(id))),
+                Some(previous) if *previous != key_values => {
+                    let keys: BTreeSet<&String> =
+                        previous.keys().chain(key_values.keys()).collect();
+                    let mut event = ClusterEvent::new(ClusterEventKind::NodeStateChanged, Some(id));
+                    event.changed_keys = keys
+                        .into_iter()
+                        .filter(|key| previous.get(*key) != key_values.get(*key))
+                        .cloned()
+                        .collect();
+                    events.push(event);
+                }
+                Some(_) => {}
+            }
+
+            match view.validate(node_state) {
+                Ok(()) => {
+                    self.rejections.remove(id);
+                }
+                Err(rejection) => {
+                    if self.rejections.get(id) != Some(&rejection) {
+                        let mut event = ClusterEvent::new(ClusterEventKind::NodeRejected, Some(id));
+                        event.rejection = Some(rejection.clone());
+                        events.push(event);
+                        self.rejections.insert(id.clone(), rejection);
+                    }
+                }
+            }
+            self.nodes.insert(id.clone(), key_values);
+        }
+
+        let mut revoked = BTreeSet::new();
+        for license in &view.revoked_licenses {
+            let key = (license.license_id.clone(), license.provider_pubkey.clone());
+            if !self.revoked.contains(&key) {
+                let mut event = ClusterEvent::new(ClusterEventKind::LicenseRevoked, None);
+                event.revoked_license = Some(license.clone());
+                events.push(event);
+            }
+            revoked.insert(key);
+        }
+        self.revoked = revoked;
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chitchat::NodeState;
+
+    use super::*;
+    use crate::utils::RevocationReason;
+
+    fn node(port: u16, key_values: serde_json::Value) -> (ChitchatId, NodeState) {
+        let id = ChitchatId::new(format!("node-{port}"), 0, ([127, 0, 0, 1], port).into());
+        let key_values: serde_json::Map<String, serde_json::Value> = key_values
+            .as_object()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(version, (key, value))| {
+                (
+                    key.clone(),
+                    serde_json::json!({ "value": value, "version": version + 1, "status": "Set" }),
+                )
+            })
+            .collect();
+        let state = serde_json::json!({
+            "chitchat_id": id,
+            "heartbeat": 1,
+            "key_values": key_values,
+            "max_version": key_values.len(),
+            "last_gc_version": 0
+        });
+        (id, serde_json::from_value(state).unwrap())
+    }
+
+    fn view(
+        live_nodes: BTreeMap<ChitchatId, NodeState>,
+        revoked_licenses: Vec<RevokedLicense>,
+    ) -> VerifiedView {
+        VerifiedView { live_nodes, revoked_licenses, ..Default::default() }
+    }
+
+    fn kinds(events: &[ClusterEvent]) -> Vec<ClusterEventKind> {
+        events.iter().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn changes_of_live_nodes_become_events() {
+        let mut tracker = ClusterTracker::default();
+        let (a, a_state) = node(1, serde_json::json!({ "pubkey": "a" }));
+        let live_nodes = view(BTreeMap::from([(a.clone(), a_state)]), vec![]);
+        // The node has no signatures, so it is rejected right away and only once
+        let events = tracker.update(&live_nodes);
+        assert_eq!(kinds(&events), [ClusterEventKind::NodeJoined, ClusterEventKind::NodeRejected]);
+        assert!(tracker.update(&live_nodes).is_empty());
+
+        let (_, a_state) = node(1, serde_json::json!({ "pubkey": "a", "version": "1" }));
+        let live_nodes = view(BTreeMap::from([(a.clone(), a_state)]), vec![]);
+        let events = tracker.update(&live_nodes);
+        assert_eq!(kinds(&events), [ClusterEventKind::NodeStateChanged]);
+        assert_eq!(events[0].changed_keys, ["version"]);
+
+        let revoked = RevokedLicense {
+            license_id: "license".to_string(),
+            provider_pubkey: "provider".to_string(),
+            timestamp: 1,
+            reason: RevocationReason::ReDelegated,
+            details: None,
+        };
+        let revoked = view(BTreeMap::new(), vec![revoked]);
+        let events = tracker.update(&revoked);
+        assert_eq!(kinds(&events), [ClusterEventKind::NodeLeft, ClusterEventKind::LicenseRevoked]);
+        assert!(tracker.update(&revoked).is_empty());
+
+        // A license that is active again and then revoked again is reported again
+        assert!(tracker.update(&view(BTreeMap::new(), vec![])).is_empty());
+        assert_eq!(kinds(&tracker.update(&revoked)), [ClusterEventKind::LicenseRevoked]);
+    }
+
+    // Ids of the events the feed sends right away
+    async fn replayed(feed: &EventFeed, last_event_id: Option<&str>) -> Vec<String> {
+        let mut events = feed.subscribe(last_event_id);
+        let mut ids = vec![];
+        while let Ok(Some(event)) =
+            tokio::time::timeout(Duration::from_millis(10), events.next()).await
+        {
+            ids.push(event.id());
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn events_are_replayed_by_epoch_and_sequence() {
+        let (sender, _) = broadcast::channel(EVENT_HISTORY);
+        let history = Arc::new(Mutex::new(History { epoch: 7, ..Default::default() }));
+        let feed = EventFeed { sender, history, task: tokio::spawn(async {}) };
+        let events = (0..3).map(|_| ClusterEvent::new(ClusterEventKind::NodeJoined, None));
+        publish(events.collect(), &feed.sender, &feed.history);
+
+        assert!(replayed(&feed, None).await.is_empty());
+        assert_eq!(replayed(&feed, Some("7-1")).await, ["7-2", "7-3"]);
+        assert!(replayed(&feed, Some("7-3")).await.is_empty());
+        // Ids of a previous process, including the plain sequence numbers of older versions
+        assert_eq!(replayed(&feed, Some("6-1")).await, ["7-1", "7-2", "7-3"]);
+        assert_eq!(replayed(&feed, Some("7-9")).await, ["7-1", "7-2", "7-3"]);
+        assert_eq!(replayed(&feed, Some("2")).await, ["7-1", "7-2", "7-3"]);
+    }
+}
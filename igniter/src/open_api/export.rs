@@ -0,0 +1,148 @@
+// Canonical form of the zerostate export. Every igniter that sees the same verified state
+// produces the same bytes: nodes are sorted by pubkey, signatures by license id, maps are
+// ordered and the JSON is compact. The SHA-256 of the body lets zerostate builders compare
+// exports of different nodes, the node can also sign the body with its wallet key.
+
+use std::collections::BTreeMap;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use ed25519_dalek::Signer;
+use ed25519_dalek::SigningKey;
+use poem_openapi::Object;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::config::LicenceSignature;
+use crate::open_api::routes::VerifiedNodeState;
+
+// Bumped on every change of the exported fields or their order
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct ZerostateExport {
+    pub format_version: u32,
+    pub nodes: Vec<ExportedNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct ExportedNode {
+    pub pubkey: String,
+    pub bls_key: String,
+    pub signatures: Vec<LicenceSignature>,
+    pub licenses: BTreeMap<String, i32>,
+    pub version: String,
+}
+
+impl ZerostateExport {
+    pub fn new(verified_state: &[VerifiedNodeState]) -> Self {
+        let mut nodes: Vec<ExportedNode> = verified_state
+            .iter()
+            .map(|node| {
+                let mut signatures = node.signatures.clone();
+                signatures.sort_by(|a, b| a.license_id.cmp(&b.license_id));
+                ExportedNode {
+                    pubkey: node.pubkey.clone(),
+                    bls_key: node.bls_key.clone(),
+                    signatures,
+                    licenses: node.licenses.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+                    version: node.version.clone(),
+                }
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+        ZerostateExport { format_version: EXPORT_FORMAT_VERSION, nodes }
+    }
+
+    // Struct fields are serialized in declaration order and maps are sorted, so the
+    // output depends on the content only
+    pub fn canonical_body(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Serialization of ZerostateExport cannot fail")
+    }
+}
+
+pub struct SignedExport {
+    pub body: Vec<u8>,
+    // Hex encoded SHA-256 of `body`
+    pub sha256: String,
+    // Base64 encoded ed25519 signature of `body` and the hex encoded public key
+    pub signature: Option<String>,
+    pub signer: Option<String>,
+}
+
+impl SignedExport {
+    pub fn new(export: &ZerostateExport, signing_key: Option<&SigningKey>) -> Self {
+        let body = export.canonical_body();
+        let sha256 = hex::encode(Sha256::digest(&body));
+        let signature = signing_key.map(|key| STANDARD.encode(key.sign(&body).to_bytes()));
+        let signer = signing_key.map(|key| hex::encode(key.verifying_key().to_bytes()));
+        SignedExport { body, sha256, signature, signer }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ed25519_dalek::Signature;
+    use ed25519_dalek::Verifier;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    fn signature(license_id: &str) -> LicenceSignature {
+        LicenceSignature {
+            license_id: license_id.to_string(),
+            license_owner_pubkey: format!("owner-{license_id}"),
+            provider_pubkey: "provider".to_string(),
+            license_proof_sig: "proof".to_string(),
+            delegation_sig: "delegation".to_string(),
+            delegation_confirm_sig: "confirmation".to_string(),
+            timestamp: 1,
+        }
+    }
+
+    fn node(pubkey: &str, license_ids: &[&str]) -> VerifiedNodeState {
+        VerifiedNodeState {
+            pubkey: pubkey.to_string(),
+            bls_key: format!("bls-{pubkey}"),
+            signatures: license_ids.iter().map(|id| signature(id)).collect(),
+            licenses: license_ids.iter().map(|id| (format!("owner-{id}"), 1)).collect(),
+            version: "0.4.1".to_string(),
+        }
+    }
+
+    #[test]
+    fn export_does_not_depend_on_the_order_of_the_state() {
+        let state = [node("b", &["3", "1"]), node("a", &["2", "4", "5"])];
+        let mut shuffled = [node("a", &["5", "2", "4"]), node("b", &["1", "3"])];
+        // HashMaps of the same content iterate in different orders
+        shuffled[0].licenses = shuffled[0].licenses.drain().collect::<HashMap<_, _>>();
+
+        let export = SignedExport::new(&ZerostateExport::new(&state), None);
+        let other = SignedExport::new(&ZerostateExport::new(&shuffled), None);
+        assert_eq!(export.body, other.body);
+        assert_eq!(export.sha256, other.sha256);
+        assert_eq!(export.sha256.len(), 64);
+
+        let parsed: ZerostateExport = serde_json::from_slice(&export.body).unwrap();
+        assert_eq!(parsed.format_version, EXPORT_FORMAT_VERSION);
+        assert_eq!(parsed.nodes[0].pubkey, "a");
+        let license_ids: Vec<&str> =
+            parsed.nodes[0].signatures.iter().map(|s| s.license_id.as_str()).collect();
+        assert_eq!(license_ids, ["2", "4", "5"]);
+    }
+
+    #[test]
+    fn export_signature_covers_the_body() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let export =
+            SignedExport::new(&ZerostateExport::new(&[node("a", &["1"])]), Some(&signing_key));
+        let signature = STANDARD.decode(export.signature.unwrap()).unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        assert!(signing_key.verifying_key().verify(&export.body, &signature).is_ok());
+        assert_eq!(export.signer.unwrap(), hex::encode(signing_key.verifying_key().to_bytes()));
+    }
+}
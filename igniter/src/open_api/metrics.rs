@@ -0,0 +1,140 @@
+// Metrics in the Prometheus text exposition format. The gauges are computed from the gossip
+// state on every scrape, only the request latencies are accumulated.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Upper bounds of the request latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+// Latencies of the API requests by OpenAPI operation and response status
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    histograms: Mutex<BTreeMap<(String, u16), Histogram>>,
+}
+
+impl RequestMetrics {
+    pub fn observe(&self, operation: &str, status: u16, elapsed: Duration) {
+        let mut histograms = self.histograms.lock().expect("request metrics lock");
+        histograms
+            .entry((operation.to_string(), status))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn render(&self, out: &mut MetricsWriter) {
+        let name = "igniter_api_request_duration_seconds";
+        out.header(name, "histogram", "Latency of the API requests");
+        let histograms = self.histograms.lock().expect("request metrics lock");
+        for ((operation, status), histogram) in histograms.iter() {
+            let status = status.to_string();
+            let labels = [("operation", operation.as_str()), ("status", status.as_str())];
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let le = bound.to_string();
+                out.sample(
+                    &format!("{name}_bucket"),
+                    &[&labels[..], &[("le", &le)]].concat(),
+                    count,
+                );
+            }
+            let all = [&labels[..], &[("le", "+Inf")]].concat();
+            out.sample(&format!("{name}_bucket"), &all, histogram.count);
+            out.sample(&format!("{name}_sum"), &labels, histogram.sum);
+            out.sample(&format!("{name}_count"), &labels, histogram.count);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    pub fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {name} {help}");
+        let _ = writeln!(self.out, "# TYPE {name} {kind}");
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{label}=\"{}\"", escape(value)))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {value}");
+    }
+
+    // A metric without labels
+    pub fn gauge(&mut self, name: &str, help: &str, value: impl std::fmt::Display) {
+        self.header(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latencies_are_rendered_as_cumulative_histogram() {
+        let metrics = RequestMetrics::default();
+        metrics.observe("state", 200, Duration::from_millis(3));
+        metrics.observe("state", 200, Duration::from_millis(300));
+        let mut out = MetricsWriter::default();
+        metrics.render(&mut out);
+        let text = out.finish();
+
+        let labels = r#"operation="state",status="200""#;
+        assert!(text.contains("# TYPE igniter_api_request_duration_seconds histogram"));
+        assert!(text.contains(&format!(
+            "igniter_api_request_duration_seconds_bucket{{{labels},le=\"0.001\"}} 0"
+        )));
+        assert!(text.contains(&format!(
+            "igniter_api_request_duration_seconds_bucket{{{labels},le=\"0.005\"}} 1"
+        )));
+        assert!(text.contains(&format!(
+            "igniter_api_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 2"
+        )));
+        assert!(text.contains(&format!("igniter_api_request_duration_seconds_count{{{labels}}} 2")));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut out = MetricsWriter::default();
+        out.sample("igniter_node_versions", &[("version", "a\"b\\c")], 1);
+        assert_eq!(out.finish(), "igniter_node_versions{version=\"a\\\"b\\\\c\"} 1\n");
+    }
+}
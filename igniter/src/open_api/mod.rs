@@ -1 +1,4 @@
+pub mod events;
+pub mod export;
+pub mod metrics;
 pub mod routes;
@@ -1,82 +1,344 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
+use std::time::Instant;
 use std::vec;
 
-use anyhow::anyhow;
 use chitchat::ChitchatId;
 use chitchat::ChitchatRef;
-use chitchat::ClusterStateSnapshot;
+use chitchat::DeletionStatus;
 use chitchat::NodeState;
+use chitchat::VersionedValue;
+use ed25519_dalek::SigningKey;
+use poem::web::sse::Event;
+use poem_openapi::param::Header;
+use poem_openapi::param::Path;
 use poem_openapi::param::Query;
+use poem_openapi::payload::Binary;
+use poem_openapi::payload::EventStream;
+use poem_openapi::payload::Json;
 use poem_openapi::payload::PlainText;
+use poem_openapi::Enum;
+use poem_openapi::Object;
 use poem_openapi::OpenApi;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::backend_keys::BackendKeyring;
+use crate::config::Config;
 use crate::config::LicenceSignature;
 use crate::config::ProxyConfig;
+use crate::config::TimestampPolicy;
+use crate::consensus::ConsensusMonitor;
+use crate::consensus::ConsensusReport;
+use crate::errors::IgniterError;
+use crate::open_api::events::ClusterEvents;
+use crate::open_api::events::EventFeed;
+use crate::open_api::export::SignedExport;
+use crate::open_api::export::ZerostateExport;
+use crate::open_api::metrics::MetricsWriter;
+use crate::open_api::metrics::RequestMetrics;
+use crate::revocations::RevocationList;
 use crate::utils::remove_with_outdated_timestamps;
 use crate::utils::ContainsVec;
+use crate::utils::LicenseConflict;
+use crate::utils::RevocationReason;
 use crate::utils::RevokedLicense;
+use crate::verification_cache::VerificationCache;
+use crate::verification_cache::VerifiedView;
 use crate::VerifiedSignatures;
 use crate::ZerostateKeys;
-use crate::BACKEND_VERIFYING_KEY;
 
 pub static DEFAULT_GOSSIP_INTERVAL: Duration = Duration::from_millis(500);
 
 pub const MAX_PROXIES: usize = 10;
 
-pub fn extract_verified_state_without_licences(
-    node_states: Vec<NodeState>,
-) -> (Vec<VerifiedNodeState>, Vec<RevokedLicense>) {
-    let mut verified_state_without_licences = vec![];
-    for state in node_states {
-        // Create hashmap from non-deleted key-values
-        let k_v: HashMap<String, String> =
-            state.key_values().map(|(k, v)| (k.into(), v.into())).collect();
-
-        match VerifiedNodeStateNoLicenses::from_gossip(k_v) {
-            Ok(data) => verified_state_without_licences.push(data),
-            Err(err) => tracing::error!("Skip invalid data: {:?}", err),
+const EVENTS_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// The gossip loop is considered stuck if the own heartbeat does not grow for this long
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ApiResponse {
+    pub cluster_id: String,
+    pub cluster_state: ClusterState,
+    pub live_nodes: Vec<NodeId>,
+    pub dead_nodes: Vec<NodeId>,
+    // License id to the id of the backend key that verified its proof
+    pub backend_key_ids: BTreeMap<String, String>,
+}
+
+// API mirrors of the chitchat types, they serialize to the same JSON
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct NodeId {
+    pub node_id: String,
+    pub generation_id: u64,
+    pub gossip_advertise_addr: String,
+}
+
+impl From<&ChitchatId> for NodeId {
+    fn from(id: &ChitchatId) -> Self {
+        NodeId {
+            node_id: id.node_id.clone(),
+            generation_id: id.generation_id,
+            gossip_advertise_addr: id.gossip_advertise_addr.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Enum)]
+pub enum ValueStatus {
+    Set,
+    Delete,
+    DeleteAfterTtl,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct GossipValue {
+    pub value: String,
+    pub version: u64,
+    pub status: ValueStatus,
+}
+
+impl From<&VersionedValue> for GossipValue {
+    fn from(value: &VersionedValue) -> Self {
+        let status = match value.status {
+            DeletionStatus::Set => ValueStatus::Set,
+            DeletionStatus::Deleted(_) => ValueStatus::Delete,
+            DeletionStatus::DeleteAfterTtl(_) => ValueStatus::DeleteAfterTtl,
         };
+        GossipValue { value: value.value.clone(), version: value.version, status }
     }
-    let (verified_state, revoked_licenses) =
-        VerifiedNodeState::from_state(verified_state_without_licences);
+}
 
-    (verified_state, revoked_licenses)
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct GossipNodeState {
+    pub chitchat_id: NodeId,
+    pub heartbeat: u64,
+    pub key_values: BTreeMap<String, GossipValue>,
+    pub max_version: u64,
+    pub last_gc_version: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ApiResponse {
-    pub cluster_id: String,
-    pub cluster_state: ClusterStateSnapshot,
-    pub live_nodes: Vec<ChitchatId>,
-    pub dead_nodes: Vec<ChitchatId>,
+impl From<&NodeState> for GossipNodeState {
+    fn from(state: &NodeState) -> Self {
+        GossipNodeState {
+            chitchat_id: state.chitchat_id().into(),
+            heartbeat: state.heartbeat().into(),
+            key_values: state
+                .key_values_including_deleted()
+                .map(|(key, value)| (key.to_string(), value.into()))
+                .collect(),
+            max_version: state.max_version(),
+            last_gc_version: state.last_gc_version(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Object)]
+pub struct ClusterState {
+    pub node_states: Vec<GossipNodeState>,
+    pub seed_addrs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct Readiness {
+    pub ready: bool,
+    // A seed node is alive and its state has been received
+    pub seed_contact: bool,
+    pub live_nodes: usize,
+    pub min_live_nodes: usize,
+    // Set if the own node state fails the checks applied by the peers
+    pub own_state_rejection: Option<NodeRejection>,
+}
+
+#[derive(poem_openapi::ApiResponse)]
+pub enum CanonicalExportResponse {
+    /// `ZerostateExport` in the canonical form, the headers hold the SHA-256 of the body and
+    /// its signature made with the wallet key of the node
+    #[oai(status = 200, content_type = "application/json")]
+    Ok(
+        Binary<Vec<u8>>,
+        #[oai(header = "X-Export-SHA256")] String,
+        #[oai(header = "X-Export-Signature")] Option<String>,
+        #[oai(header = "X-Export-Signer")] Option<String>,
+    ),
+}
+
+#[derive(poem_openapi::ApiResponse)]
+pub enum HealthResponse {
+    #[oai(status = 200)]
+    Ok(PlainText<String>),
+    #[oai(status = 503)]
+    Unavailable(PlainText<String>),
+}
+
+#[derive(poem_openapi::ApiResponse)]
+pub enum ReadyResponse {
+    #[oai(status = 200)]
+    Ready(Json<Readiness>),
+    #[oai(status = 503)]
+    NotReady(Json<Readiness>),
 }
 
 pub struct Api {
     pub chitchat: ChitchatRef,
+    pub request_metrics: Arc<RequestMetrics>,
+    verified: VerificationCache,
+    events: EventFeed,
+    consensus: ConsensusMonitor,
+    min_live_nodes: usize,
+    // Wallet key of the node, revoked licenses of this node are reported in metrics
+    wallet_pubkey: String,
+    // Own heartbeat as it was last seen and when it changed
+    last_heartbeat: Mutex<(u64, Instant)>,
+    // Wallet key of the node, signs the canonical export
+    signing_key: Option<SigningKey>,
 }
 
 impl Api {
-    pub fn new(chitchat: ChitchatRef) -> Self {
-        Self { chitchat }
+    pub fn new(
+        chitchat: ChitchatRef,
+        verified: VerificationCache,
+        config: &Config,
+        wallet_pubkey: &str,
+    ) -> Self {
+        Self {
+            events: EventFeed::spawn(verified.clone()),
+            consensus: ConsensusMonitor::spawn(chitchat.clone(), verified.clone()),
+            chitchat,
+            verified,
+            request_metrics: Arc::default(),
+            min_live_nodes: config.min_live_nodes,
+            wallet_pubkey: wallet_pubkey.to_string(),
+            last_heartbeat: Mutex::new((0, Instant::now())),
+            signing_key: None,
+        }
     }
 
-    pub fn get_verified_state(&self) -> (Vec<VerifiedNodeState>, Vec<RevokedLicense>) {
-        let node_states = self.chitchat.lock().state_snapshot().node_states;
-        extract_verified_state_without_licences(node_states)
+    pub fn with_signing_key(self, signing_key: Option<SigningKey>) -> Self {
+        Self { signing_key, ..self }
     }
-}
 
-#[OpenApi]
-impl Api {
-    /// Chitchat state
-    #[oai(path = "/", method = "get")]
-    async fn index(&self) -> PlainText<String> {
-        // We need verified state to compare derived licenses with the licenses in the current state
-        let (verified_state, _) = self.get_verified_state();
+    pub fn metrics(&self) -> String {
+        let view = self.verified.get();
+        let (live_nodes, dead_nodes, scheduled_for_deletion, gossip_rounds, node_states) = {
+            let mut chitchat_guard = self.chitchat.lock();
+            let gossip_rounds: u64 = chitchat_guard.self_node_state().heartbeat().into();
+            (
+                chitchat_guard.live_nodes().count(),
+                chitchat_guard.dead_nodes().count(),
+                chitchat_guard.scheduled_for_deletion_nodes().count(),
+                gossip_rounds,
+                chitchat_guard.state_snapshot().node_states,
+            )
+        };
+
+        let mut rejected = 0;
+        let mut versions = BTreeMap::<String, usize>::new();
+        for node_state in &node_states {
+            if view.validate(node_state).is_err() {
+                rejected += 1;
+            }
+            if let Some(version) = node_state.get(&ZerostateKeys::Version.to_string()) {
+                *versions.entry(version.to_string()).or_default() += 1;
+            }
+        }
+        let licenses: i64 = view
+            .verified_state
+            .iter()
+            .flat_map(|node| node.licenses.values())
+            .map(|count| i64::from(*count))
+            .sum();
+        let own_revoked_licenses = view
+            .revoked_licenses
+            .iter()
+            .filter(|license| license.provider_pubkey == self.wallet_pubkey)
+            .count();
+
+        let mut out = MetricsWriter::default();
+        out.header("igniter_nodes", "gauge", "Nodes known to gossip by liveness");
+        out.sample("igniter_nodes", &[("state", "live")], live_nodes);
+        out.sample("igniter_nodes", &[("state", "dead")], dead_nodes);
+        out.sample("igniter_nodes", &[("state", "scheduled_for_deletion")], scheduled_for_deletion);
+        out.header("igniter_node_states", "gauge", "Gossip node states by verification result");
+        out.sample("igniter_node_states", &[("result", "verified")], node_states.len() - rejected);
+        out.sample("igniter_node_states", &[("result", "rejected")], rejected);
+        out.gauge("igniter_verified_licenses", "Licenses in the verified state", licenses);
+        out.gauge(
+            "igniter_revoked_licenses",
+            "Revoked licenses of this node",
+            own_revoked_licenses,
+        );
+        out.gauge(
+            "igniter_license_conflicts",
+            "Delegations dropped because another node has the same license and timestamp",
+            view.conflicts.len(),
+        );
+        out.gauge(
+            "igniter_revocation_list_version",
+            "Version of the active backend revocation list, 0 if there is none",
+            view.revocations.version,
+        );
+        out.header("igniter_node_versions", "gauge", "Gossip node states by igniter version");
+        for (version, count) in &versions {
+            out.sample("igniter_node_versions", &[("version", version)], count);
+        }
+        out.header("igniter_gossip_rounds_total", "counter", "Gossip rounds of this node");
+        out.sample("igniter_gossip_rounds_total", &[], gossip_rounds);
+        self.request_metrics.render(&mut out);
+        out.finish()
+    }
+
+    // The own heartbeat is incremented on every gossip round
+    pub fn gossip_running(&self) -> bool {
+        let heartbeat = self.chitchat.lock().self_node_state().heartbeat().into();
+        let mut last_heartbeat = self.last_heartbeat.lock().expect("heartbeat lock");
+        if last_heartbeat.0 != heartbeat {
+            *last_heartbeat = (heartbeat, Instant::now());
+        }
+        last_heartbeat.1.elapsed() < HEARTBEAT_TIMEOUT
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let (self_id, seed_addrs, live_nodes, own_state) = {
+            let mut chitchat_guard = self.chitchat.lock();
+            (
+                chitchat_guard.self_chitchat_id().clone(),
+                chitchat_guard.seed_nodes(),
+                chitchat_guard.live_nodes().cloned().collect::<Vec<_>>(),
+                chitchat_guard.self_node_state().clone(),
+            )
+        };
+        // A seed node does not have to reach itself, a cluster of seeds still needs the others
+        let other_seeds = seed_addrs.iter().any(|addr| *addr != self_id.gossip_advertise_addr);
+        let seed_contact = !other_seeds
+            || live_nodes
+                .iter()
+                .any(|id| *id != self_id && seed_addrs.contains(&id.gossip_advertise_addr));
+        let own_state_rejection = self.verified.get().validate(&own_state).err();
+        Readiness {
+            ready: seed_contact
+                && live_nodes.len() >= self.min_live_nodes
+                && own_state_rejection.is_none(),
+            seed_contact,
+            live_nodes: live_nodes.len(),
+            min_live_nodes: self.min_live_nodes,
+            own_state_rejection,
+        }
+    }
+
+    pub fn get_verified_state(&self) -> Arc<VerifiedView> {
+        self.verified.get()
+    }
 
+    // Chitchat state without the nodes that fail verification
+    pub fn cluster_state(&self) -> ApiResponse {
+        let view = self.get_verified_state();
         let (cluster_id, live_nodes, dead_nodes, mut state_snapshot) = {
             let chitchat_guard = self.chitchat.lock();
             (
@@ -87,65 +349,219 @@ impl Api {
             )
         };
 
-        // Parse each node_state to check that:
-        // 1. it has all required properties
-        // 2. signatures are valid
-        // 3. signatures and `licences` match
-        // 4. Check that proxies contains valid socket addresses
-        state_snapshot.node_states.retain(|node_state| {
-            let k_v: HashMap<String, String> =
-                node_state.key_values().map(|(k, v)| (k.into(), v.into())).collect();
-
-            // Check that node_state has all required properties
-            match VerifiedNodeStateNoLicenses::from_gossip(k_v.clone()) {
-                Ok(VerifiedNodeStateNoLicenses { pubkey, .. }) => {
-                    let derived_licences =
-                        match verified_state.iter().find(|s| s.pubkey == pubkey) {
-                            Some(x) => x.licenses.clone(),
-                            None => {
-                                tracing::error!("Skip node with pubkey {pubkey}. It is not included in verified state");
-                                return false;
-                            }
-                        };
-
-                    if let Err(err) = validate_licenses(&k_v, &derived_licences) {
-                        tracing::error!(
-                            "Skip node with pubkey {pubkey}. It provides invalid licenses info: {err}",
-                        );
-                        return false;
-                    };
-
-                    if !check_proxy_socket_addresses(k_v.get(&ZerostateKeys::Proxies.to_string())) {
-                        tracing::error!(
-                            "Skip node with pubkey {pubkey}. It provides invalid proxy info",
-                        );
-                        return false;
-                    }
-                    true
-                }
-                Err(error) => {
-                    tracing::error!("Gossip node state can't be parsed: {:?}", error);
-                    false
-                }
+        state_snapshot.node_states.retain(|node_state| match view.validate(node_state) {
+            Ok(()) => true,
+            Err(rejection) => {
+                tracing::error!("Skip node {:?}: {rejection}", node_state.chitchat_id());
+                false
             }
         });
 
-        let res = ApiResponse { cluster_id, cluster_state: state_snapshot, live_nodes, dead_nodes };
+        ApiResponse {
+            cluster_id,
+            cluster_state: ClusterState {
+                node_states: state_snapshot.node_states.iter().map(Into::into).collect(),
+                seed_addrs: state_snapshot.seed_addrs.iter().map(ToString::to_string).collect(),
+            },
+            live_nodes: live_nodes.iter().map(Into::into).collect(),
+            dead_nodes: dead_nodes.iter().map(Into::into).collect(),
+            backend_key_ids: view.backend_key_ids.clone(),
+        }
+    }
+
+    // Raw gossip state of the matching nodes together with the result of their validation
+    pub fn node_details(&self, filter: impl Fn(&NodeState) -> bool) -> Vec<NodeDetails> {
+        let view = self.get_verified_state();
+        let pubkey_key = ZerostateKeys::Pubkey.to_string();
+        let (live_nodes, state_snapshot) = {
+            let chitchat_guard = self.chitchat.lock();
+            (
+                chitchat_guard.live_nodes().cloned().collect::<HashSet<_>>(),
+                chitchat_guard.state_snapshot(),
+            )
+        };
+        state_snapshot
+            .node_states
+            .iter()
+            .filter(|node_state| filter(node_state))
+            .map(|node_state| {
+                let rejection = view.validate(node_state).err();
+                let pubkey = node_state.get(&pubkey_key);
+                let backend_key_ids = view
+                    .verified_state
+                    .iter()
+                    .filter(|node| Some(node.pubkey.as_str()) == pubkey)
+                    .flat_map(|node| &node.signatures)
+                    .filter_map(|sig| {
+                        let key_id = view.backend_key_ids.get(&sig.license_id)?;
+                        Some((sig.license_id.clone(), key_id.clone()))
+                    })
+                    .collect();
+                let state = GossipNodeState::from(node_state);
+                NodeDetails {
+                    is_live: live_nodes.contains(node_state.chitchat_id()),
+                    chitchat_id: state.chitchat_id,
+                    heartbeat: state.heartbeat,
+                    key_values: state.key_values,
+                    valid: rejection.is_none(),
+                    rejection,
+                    backend_key_ids,
+                }
+            })
+            .collect()
+    }
+
+    pub fn revoked_licenses(&self, provider_pubkey: &str) -> Vec<RevokedLicense> {
+        self.get_verified_state()
+            .revoked_licenses
+            .iter()
+            .filter(|license| license.provider_pubkey == provider_pubkey)
+            .cloned()
+            .collect()
+    }
+}
+
+#[OpenApi]
+impl Api {
+    /// Chitchat state without the nodes that fail verification
+    #[oai(path = "/state", method = "get", operation_id = "state")]
+    async fn state(&self) -> Json<ApiResponse> {
+        Json(self.cluster_state())
+    }
+
+    /// Licenses of the provider that have been re-delegated to another node or revoked by the backend
+    #[oai(path = "/revoked-licenses", method = "get", operation_id = "revoked_licenses")]
+    async fn revoked_licenses_json(
+        &self,
+        provider_pubkey: Query<String>,
+    ) -> Json<Vec<RevokedLicense>> {
+        Json(self.revoked_licenses(&provider_pubkey.0))
+    }
+
+    /// Licenses delegated to several nodes with the same timestamp and which delegation is kept
+    #[oai(path = "/conflicts", method = "get", operation_id = "conflicts")]
+    async fn conflicts(&self) -> Json<Vec<LicenseConflict>> {
+        Json(self.get_verified_state().conflicts.clone())
+    }
+
+    /// Gossip state and validation verdict of the nodes with the given owner pubkey
+    #[oai(path = "/nodes/:pubkey", method = "get", operation_id = "nodes_by_pubkey")]
+    async fn nodes_by_pubkey(&self, pubkey: Path<String>) -> NodesResponse {
+        let pubkey_key = ZerostateKeys::Pubkey.to_string();
+        let nodes =
+            self.node_details(|node_state| node_state.get(&pubkey_key) == Some(pubkey.0.as_str()));
+        NodesResponse::new(nodes, format!("No nodes with pubkey {}", pubkey.0))
+    }
+
+    /// Gossip state and validation verdict of the node with the given advertise address
+    #[oai(path = "/nodes/by-addr/:advertise_addr", method = "get", operation_id = "nodes_by_addr")]
+    async fn nodes_by_addr(&self, advertise_addr: Path<String>) -> NodesResponse {
+        let Ok(addr) = advertise_addr.0.parse::<SocketAddr>() else {
+            return NodesResponse::BadRequest(PlainText(format!(
+                "Invalid advertise address {}",
+                advertise_addr.0
+            )));
+        };
+        let nodes =
+            self.node_details(|node_state| node_state.chitchat_id().gossip_advertise_addr == addr);
+        NodesResponse::new(nodes, format!("No nodes with advertise address {addr}"))
+    }
+
+    /// Liveness: the process is up and the gossip loop is running
+    #[oai(path = "/health", method = "get", operation_id = "health")]
+    async fn health(&self) -> HealthResponse {
+        if self.gossip_running() {
+            HealthResponse::Ok(PlainText("OK".to_string()))
+        } else {
+            HealthResponse::Unavailable(PlainText("Gossip loop is not running".to_string()))
+        }
+    }
+
+    /// Readiness: the node has joined the cluster and its own state is accepted by the peers
+    #[oai(path = "/ready", method = "get", operation_id = "ready")]
+    async fn ready(&self) -> ReadyResponse {
+        let readiness = self.readiness();
+        if readiness.ready {
+            ReadyResponse::Ready(Json(readiness))
+        } else {
+            ReadyResponse::NotReady(Json(readiness))
+        }
+    }
 
+    /// Stream of cluster changes, a reconnecting client continues after `Last-Event-ID`
+    #[oai(path = "/events", method = "get", operation_id = "events")]
+    async fn events(
+        &self,
+        #[oai(name = "Last-Event-ID")] last_event_id: Header<Option<String>>,
+    ) -> EventStream<ClusterEvents> {
+        EventStream::new(self.events.subscribe(last_event_id.0.as_deref()))
+            .keep_alive(EVENTS_KEEP_ALIVE)
+            .to_event(|event| {
+                Event::message(serde_json::to_string(&event).expect("Serialization can't fail"))
+                    .event_type(event.kind.to_string())
+                    .id(event.id())
+            })
+    }
+
+    /// Metrics in the Prometheus text format
+    #[oai(path = "/metrics", method = "get", operation_id = "metrics")]
+    async fn metrics_text(&self) -> PlainText<String> {
+        PlainText(self.metrics())
+    }
+
+    /// Export data to create zerostate
+    #[oai(path = "/export", method = "get", operation_id = "export")]
+    async fn export(&self) -> PlainText<String> {
         PlainText(
-            serde_json::to_string_pretty(&res).expect("Serialization of ApiResponse cannot fail"),
+            serde_json::to_string_pretty(&self.get_verified_state().verified_state)
+                .expect("Serialization can't fail"),
         )
     }
 
-    /// returns all licenses that have been re-delegated to another node
-    #[oai(path = "/getRevokedLicenses", method = "get")]
-    async fn get_revoked_licenses(&self, provider_pubkey: Query<String>) -> PlainText<String> {
-        let pubkey = provider_pubkey.0;
+    /// Export data to create zerostate as typed JSON
+    #[oai(path = "/export/json", method = "get", operation_id = "export_json")]
+    async fn export_json(&self) -> Json<Vec<VerifiedNodeState>> {
+        Json(self.get_verified_state().verified_state.clone())
+    }
+
+    /// Export in the canonical form, the same on every node that sees the same verified state
+    #[oai(path = "/export/canonical", method = "get", operation_id = "export_canonical")]
+    async fn export_canonical(&self, sign: Query<Option<bool>>) -> CanonicalExportResponse {
+        let view = self.get_verified_state();
+        let signing_key = self.signing_key.as_ref().filter(|_| sign.0.unwrap_or(false));
+        let export = SignedExport::new(&ZerostateExport::new(&view.verified_state), signing_key);
+        CanonicalExportResponse::Ok(
+            Binary(export.body),
+            export.sha256,
+            export.signature,
+            export.signer,
+        )
+    }
+
+    /// Live nodes grouped by the hash of the canonical export they report
+    #[oai(path = "/consensus", method = "get", operation_id = "consensus")]
+    async fn consensus(&self) -> Json<ConsensusReport> {
+        Json(self.consensus.report())
+    }
 
-        let (_, revoked_licenses) = self.get_verified_state();
-        let your_revoked_licenses: Vec<RevokedLicense> =
-            revoked_licenses.into_iter().filter(|elem| elem.provider_pubkey == pubkey).collect();
+    /// Chitchat state, use `/state` instead
+    #[oai(path = "/", method = "get", operation_id = "index", deprecated)]
+    async fn index(&self) -> PlainText<String> {
+        PlainText(
+            serde_json::to_string_pretty(&self.cluster_state())
+                .expect("Serialization of ApiResponse cannot fail"),
+        )
+    }
 
+    /// returns all licenses that have been re-delegated to another node, use `/revoked-licenses` instead
+    #[oai(
+        path = "/getRevokedLicenses",
+        method = "get",
+        operation_id = "get_revoked_licenses",
+        deprecated
+    )]
+    async fn get_revoked_licenses(&self, provider_pubkey: Query<String>) -> PlainText<String> {
+        let your_revoked_licenses = self.revoked_licenses(&provider_pubkey.0);
         if your_revoked_licenses.is_empty() {
             return PlainText(
                 serde_json::to_string_pretty(&"No revoked licenses found")
@@ -156,15 +572,118 @@ impl Api {
             serde_json::to_string_pretty(&your_revoked_licenses).expect("Serialization can't fail"),
         )
     }
+}
 
-    /// Export data to create zerostate
-    #[oai(path = "/export", method = "get")]
-    async fn export(&self) -> PlainText<String> {
-        let (verified_state, _) = self.get_verified_state();
-        PlainText(serde_json::to_string_pretty(&verified_state).expect("Serialization can't fail"))
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Enum, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ValidationStep {
+    // Required keys are present and can be parsed
+    Fields,
+    // Every license passes the signature checks
+    Signatures,
+    // Delegation timestamps are neither too far in the future nor below the minimum
+    Timestamps,
+    // The node is a part of the verified state
+    VerifiedState,
+    // `licenses` match `signatures`
+    Licenses,
+    // `proxies` are valid
+    Proxies,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Object)]
+pub struct NodeRejection {
+    pub step: ValidationStep,
+    pub reason: String,
+}
+
+impl NodeRejection {
+    fn new(step: ValidationStep, reason: impl ToString) -> Self {
+        NodeRejection { step, reason: reason.to_string() }
+    }
+}
+
+impl std::fmt::Display for NodeRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} check failed: {}", self.step, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
+pub struct NodeDetails {
+    pub chitchat_id: NodeId,
+    pub is_live: bool,
+    pub heartbeat: u64,
+    pub key_values: BTreeMap<String, GossipValue>,
+    pub valid: bool,
+    // The first failed validation step, the node is left out of `/state` and `/export`
+    pub rejection: Option<NodeRejection>,
+    // Backend key ids of the licenses the node holds in the verified state
+    pub backend_key_ids: BTreeMap<String, String>,
+}
+
+#[derive(poem_openapi::ApiResponse)]
+pub enum NodesResponse {
+    #[oai(status = 200)]
+    Ok(Json<Vec<NodeDetails>>),
+    #[oai(status = 400)]
+    BadRequest(PlainText<String>),
+    #[oai(status = 404)]
+    NotFound(PlainText<String>),
+}
+
+impl NodesResponse {
+    fn new(nodes: Vec<NodeDetails>, not_found: String) -> Self {
+        if nodes.is_empty() {
+            NodesResponse::NotFound(PlainText(not_found))
+        } else {
+            NodesResponse::Ok(Json(nodes))
+        }
     }
 }
 
+// Checks applied to every node state before it is shown to the API clients:
+// 1. it has all required properties
+// 2. signatures are valid and the delegation timestamps satisfy the policy
+// 3. signatures and `licences` match
+// 4. Check that proxies contains valid socket addresses
+pub fn validate_node(
+    k_v: &HashMap<String, String>,
+    verified_state: &[VerifiedNodeState],
+    timestamps: &TimestampPolicy,
+    backend_keys: &BackendKeyring,
+) -> Result<(), NodeRejection> {
+    let node = VerifiedNodeStateNoLicenses::from_gossip(k_v.clone(), timestamps, backend_keys)?;
+    validate_parsed_node(&node, k_v, verified_state)
+}
+
+// Checks 3 and 4 for the node state that has passed `from_gossip`
+pub(crate) fn validate_parsed_node(
+    node: &VerifiedNodeStateNoLicenses,
+    k_v: &HashMap<String, String>,
+    verified_state: &[VerifiedNodeState],
+) -> Result<(), NodeRejection> {
+    if !verified_state.iter().any(|s| s.pubkey == node.pubkey) {
+        return Err(NodeRejection::new(
+            ValidationStep::VerifiedState,
+            format!("pubkey {} is not included in verified state", node.pubkey),
+        ));
+    }
+
+    // The verified state lacks the licenses delegated to other nodes, the node is checked
+    // against all of its own signatures
+    let licenses = Licences::derive_licences(&VerifiedSignatures::from_checked_state(node));
+    validate_licenses(k_v, &licenses.inner)
+        .map_err(|err| NodeRejection::new(ValidationStep::Licenses, err))?;
+
+    if !check_proxy_socket_addresses(k_v.get(&ZerostateKeys::Proxies.to_string())) {
+        return Err(NodeRejection::new(ValidationStep::Proxies, "invalid proxy info"));
+    }
+    Ok(())
+}
+
 pub fn check_proxy_socket_addresses(str_value: Option<&String>) -> bool {
     match str_value {
         Some(str_value) => match serde_json::from_str::<Vec<ProxyConfig>>(str_value) {
@@ -212,6 +731,11 @@ pub struct VerifiedNodeStateNoLicenses {
     bls_key: String,
     signatures: Vec<LicenceSignature>,
     version: String,
+    #[serde(default)]
+    backend_key_ids: BTreeMap<String, String>,
+    // Chitchat generation of the node state the node was parsed from
+    #[serde(skip)]
+    generation: u64,
 }
 
 impl VerifiedNodeStateNoLicenses {
@@ -219,37 +743,50 @@ impl VerifiedNodeStateNoLicenses {
         self.signatures.clone()
     }
 
-    fn from_gossip(section: HashMap<String, String>) -> anyhow::Result<Self> {
-        let pubkey = section
-            .get(&ZerostateKeys::Pubkey.to_string())
-            .ok_or_else(|| anyhow!("Missing required field: pubkey"))?
-            .to_string();
+    pub fn get_backend_key_ids(&self) -> BTreeMap<String, String> {
+        self.backend_key_ids.clone()
+    }
 
-        let bls_key = section
-            .get(&ZerostateKeys::BlsPubkey.to_string())
-            .ok_or_else(|| anyhow!("Missing required field: bls_pubkey"))?
-            .to_string();
+    pub(crate) fn from_gossip(
+        section: HashMap<String, String>,
+        timestamps: &TimestampPolicy,
+        backend_keys: &BackendKeyring,
+    ) -> Result<Self, NodeRejection> {
+        let field = |key: ZerostateKeys| {
+            section.get(&key.to_string()).ok_or_else(|| {
+                NodeRejection::new(ValidationStep::Fields, format!("Missing required field: {key}"))
+            })
+        };
+        let pubkey = field(ZerostateKeys::Pubkey)?.to_string();
+        let bls_key = field(ZerostateKeys::BlsPubkey)?.to_string();
 
-        let signatures: Vec<LicenceSignature> = serde_json::from_str(
-            section
-                .get(&ZerostateKeys::Signatures.to_string())
-                .ok_or_else(|| anyhow!("Missing required field: signatures"))?,
-        )?;
+        let signatures: Vec<LicenceSignature> =
+            serde_json::from_str(field(ZerostateKeys::Signatures)?)
+                .map_err(|err| NodeRejection::new(ValidationStep::Fields, err))?;
 
         let verified_signatures =
-            VerifiedSignatures::create(&signatures, &BACKEND_VERIFYING_KEY, &pubkey, &bls_key)?;
+            VerifiedSignatures::create(&signatures, backend_keys, &pubkey, &bls_key, timestamps)
+                .map_err(|err| match err {
+                    IgniterError::FutureTimestamp { .. } | IgniterError::StaleTimestamp { .. } => {
+                        NodeRejection::new(ValidationStep::Timestamps, err)
+                    }
+                    _ => NodeRejection::new(ValidationStep::Signatures, err),
+                })?;
 
-        let version = section
-            .get(&ZerostateKeys::Version.to_string())
-            .ok_or_else(|| anyhow!("Missing required field: version"))?
-            .to_string();
+        let version = field(ZerostateKeys::Version)?.to_string();
         Ok(VerifiedNodeStateNoLicenses {
             pubkey,
             bls_key,
             signatures: verified_signatures.get().clone(),
             version,
+            backend_key_ids: verified_signatures.backend_key_ids().clone(),
+            generation: 0,
         })
     }
+
+    pub(crate) fn with_generation(self, generation: u64) -> Self {
+        Self { generation, ..self }
+    }
 }
 
 impl ContainsVec<LicenceSignature> for VerifiedNodeStateNoLicenses {
@@ -264,6 +801,10 @@ impl ContainsVec<LicenceSignature> for VerifiedNodeStateNoLicenses {
     fn get_pk(&self) -> String {
         self.pubkey.clone()
     }
+
+    fn get_generation(&self) -> u64 {
+        self.generation
+    }
 }
 pub struct Licences {
     inner: HashMap<String, i32>,
@@ -283,18 +824,41 @@ impl Licences {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
 pub struct VerifiedNodeState {
-    pubkey: String,
-    bls_key: String,
-    signatures: Vec<LicenceSignature>,
-    licenses: HashMap<String, i32>,
-    version: String,
+    pub(crate) pubkey: String,
+    pub(crate) bls_key: String,
+    pub(crate) signatures: Vec<LicenceSignature>,
+    pub(crate) licenses: HashMap<String, i32>,
+    pub(crate) version: String,
 }
 
 impl VerifiedNodeState {
-    pub fn from_state(state: Vec<VerifiedNodeStateNoLicenses>) -> (Vec<Self>, Vec<RevokedLicense>) {
-        let (_, problem) = remove_with_outdated_timestamps(state.clone());
+    // Licenses revoked by the backend are dropped, every other license is kept on a single
+    // node, see `remove_with_outdated_timestamps`
+    pub fn from_state(
+        mut state: Vec<VerifiedNodeStateNoLicenses>,
+        revocations: &RevocationList,
+    ) -> (Vec<Self>, Vec<RevokedLicense>, Vec<LicenseConflict>) {
+        let revoked = revocations.by_license_id();
+        let mut revoked_by_backend = vec![];
+        for node_state in &mut state {
+            node_state.signatures.retain(|sig| {
+                let Some(revocation) = revoked.get(sig.license_id.as_str()) else {
+                    return true;
+                };
+                revoked_by_backend.push(RevokedLicense {
+                    license_id: sig.license_id.clone(),
+                    provider_pubkey: node_state.pubkey.clone(),
+                    timestamp: sig.timestamp,
+                    reason: RevocationReason::RevokedByBackend,
+                    details: revocation.reason.clone(),
+                });
+                false
+            });
+        }
+        let (state, mut problem, conflicts) = remove_with_outdated_timestamps(state);
+        problem.extend(revoked_by_backend);
 
         let mut verified_state = vec![];
 
@@ -311,13 +875,226 @@ impl VerifiedNodeState {
             });
         }
 
-        (verified_state, problem)
+        (verified_state, problem, conflicts)
+    }
+
+    // License id to the id of the backend key that verified its proof, for the licenses kept in
+    // `verified_state`. The key ids are not part of the zerostate export.
+    pub(crate) fn backend_key_ids<'a>(
+        nodes: impl Iterator<Item = &'a VerifiedNodeStateNoLicenses>,
+        verified_state: &[Self],
+    ) -> BTreeMap<String, String> {
+        let kept: HashSet<(&str, &str)> = verified_state
+            .iter()
+            .flat_map(|node| {
+                node.signatures.iter().map(|sig| (node.pubkey.as_str(), sig.license_id.as_str()))
+            })
+            .collect();
+        nodes
+            .flat_map(|node| {
+                node.backend_key_ids
+                    .iter()
+                    .filter(|(license_id, _)| kept.contains(&(&node.pubkey, license_id)))
+                    .map(|(license_id, key_id)| (license_id.clone(), key_id.clone()))
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::gossip::test_chitchat;
+    use crate::network::NetworkProfile;
+    use crate::revocations::LicenseRevocation;
+
+    #[test]
+    fn gossip_node_state_keeps_chitchat_json_format() {
+        let json = serde_json::json!({
+            "chitchat_id": {
+                "node_id": "server:127.0.0.1:10000-cool-id",
+                "generation_id": 1744375960,
+                "gossip_advertise_addr": "127.0.0.1:10000"
+            },
+            "heartbeat": 42,
+            "key_values": {
+                "pubkey": { "value": "abc", "version": 1, "status": "Set" },
+                "proxies": { "value": "[]", "version": 3, "status": "Delete" }
+            },
+            "max_version": 3,
+            "last_gc_version": 0
+        });
+        let node_state: NodeState = serde_json::from_value(json.clone()).unwrap();
+        let api_state = GossipNodeState::from(&node_state);
+        assert_eq!(serde_json::to_value(&api_state).unwrap(), json);
+    }
+
+    fn valid_node_key_values() -> HashMap<String, String> {
+        let signature = serde_json::json!([{
+            "license_id": "5e0d534d-98fd-4024-87b8-8c45414f6e9a",
+            "license_owner_pubkey": "37d545d8725f290b1dcff6e06ad7649a6264249a3202354330bb47da90c7b41f",
+            "provider_pubkey": "8e962b104119b17ab09e9aa91ff17e5816f65bb66daa6c14b8ca130f4f0bfcc0",
+            "license_proof_sig": "c6F8qZ52LNeLLKdrVll5F1/U9eGGPUzJMZw7JcKWSzbO/DmmNXkWlDW+k3GwD1giLMxUPbjPzegqYoLoKOThAg==",
+            "delegation_sig": "00lhq1wiiCs10ISYz2AkrZX9M0TU4YaNba3wG7oCeEFUP6uOav5kGesqntIQ+AKL5Y3nkVw+redFxQbOEuM1Dg==",
+            "delegation_confirm_sig": "QCd1iMgEUOd7unQ2Qi1v8EyLNIwphFc2hct+/cAsdAT7VUxUNlNmhbo6SKNHyvX5OKnrpIBf2d5JgQNZj3ueDw==",
+            "timestamp": 1744375960
+        }]);
+        HashMap::from([
+            (
+                ZerostateKeys::Pubkey.to_string(),
+                "3ef72c59a33ba75a484cfb126bd9e55db267cbd944110374d0b78a9e474c6c87".to_string(),
+            ),
+            (
+                ZerostateKeys::BlsPubkey.to_string(),
+                "8cf7d141cade81a44c8bc58a02b0448e85e77d47d9c644adfe3512d3c5fcdc2a028cfb96aff704a70f2cce27c96cd706".to_string(),
+            ),
+            (ZerostateKeys::Signatures.to_string(), signature.to_string()),
+            (ZerostateKeys::Version.to_string(), "0.1.0".to_string()),
+            (
+                ZerostateKeys::Licenses.to_string(),
+                r#"{"37d545d8725f290b1dcff6e06ad7649a6264249a3202354330bb47da90c7b41f":1}"#
+                    .to_string(),
+            ),
+            (
+                ZerostateKeys::Proxies.to_string(),
+                r#"[{"socket_address":"127.0.0.1:8080"}]"#.to_string(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn validation_names_the_failing_step() {
+        let k_v = valid_node_key_values();
+        let policy = TimestampPolicy::default();
+        let keys = BackendKeyring::from_config(&NetworkProfile::devnet(), &[]).unwrap();
+        let node = VerifiedNodeStateNoLicenses::from_gossip(k_v.clone(), &policy, &keys).unwrap();
+        let (verified_state, ..) =
+            VerifiedNodeState::from_state(vec![node.clone()], &RevocationList::default());
+        assert!(validate_node(&k_v, &verified_state, &policy, &keys).is_ok());
+        let key_ids = VerifiedNodeState::backend_key_ids([&node].into_iter(), &verified_state);
+        assert_eq!(key_ids.values().map(String::as_str).collect::<Vec<_>>(), ["dev"]);
+
+        let failed_step = |key: ZerostateKeys, value: Option<&str>| {
+            let mut k_v = k_v.clone();
+            match value {
+                Some(value) => k_v.insert(key.to_string(), value.to_string()),
+                None => k_v.remove(&key.to_string()),
+            };
+            validate_node(&k_v, &verified_state, &policy, &keys).unwrap_err().step
+        };
+        assert_eq!(failed_step(ZerostateKeys::Version, None), ValidationStep::Fields);
+        let broken = k_v[&ZerostateKeys::Signatures.to_string()].replace("c6F8", "AAAA");
+        assert_eq!(
+            failed_step(ZerostateKeys::Signatures, Some(&broken)),
+            ValidationStep::Signatures
+        );
+        assert_eq!(failed_step(ZerostateKeys::Licenses, Some("{}")), ValidationStep::Licenses);
+        assert_eq!(
+            failed_step(ZerostateKeys::Proxies, Some(r#"[{"socket_address":"a.b.c.d:1"}]"#)),
+            ValidationStep::Proxies
+        );
+        let min_timestamp = Some(1744375961);
+        let stale = TimestampPolicy { min_timestamp, ..policy };
+        let rejection = validate_node(&k_v, &verified_state, &stale, &keys).unwrap_err();
+        assert_eq!(rejection.step, ValidationStep::Timestamps);
+        let rejection = validate_node(&k_v, &[], &policy, &keys).unwrap_err();
+        assert_eq!(rejection.step, ValidationStep::VerifiedState);
+    }
+
+    #[test]
+    fn licenses_revoked_by_backend_are_excluded() {
+        let k_v = valid_node_key_values();
+        let policy = TimestampPolicy::default();
+        let keys = BackendKeyring::from_config(&NetworkProfile::devnet(), &[]).unwrap();
+        let node = VerifiedNodeStateNoLicenses::from_gossip(k_v.clone(), &policy, &keys).unwrap();
+        let license_id = node.signatures[0].license_id.clone();
+        let revocations = RevocationList {
+            version: 1,
+            issued_at: 0,
+            licenses: vec![LicenseRevocation {
+                license_id: license_id.clone(),
+                reason: Some("refunded".to_string()),
+            }],
+        };
+
+        let (verified_state, revoked, conflicts) =
+            VerifiedNodeState::from_state(vec![node], &revocations);
+        assert!(verified_state.is_empty());
+        assert!(conflicts.is_empty());
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].license_id, license_id);
+        assert_eq!(revoked[0].reason, RevocationReason::RevokedByBackend);
+        assert_eq!(revoked[0].details.as_deref(), Some("refunded"));
+        let rejection = validate_node(&k_v, &verified_state, &policy, &keys).unwrap_err();
+        assert_eq!(rejection.step, ValidationStep::VerifiedState);
+    }
+
+    #[tokio::test]
+    async fn readiness_requires_live_nodes_and_valid_own_state() {
+        let handle =
+            test_chitchat("test", 10000, valid_node_key_values().into_iter().collect()).await;
+        let mut config: Config = serde_yaml::from_str("auto_update: false").unwrap();
+        config.min_live_nodes = 1;
+        let verified = VerificationCache::spawn(
+            handle.chitchat(),
+            config.timestamp_policy,
+            Arc::new(BackendKeyring::from_config(&NetworkProfile::devnet(), &[]).unwrap()),
+            tokio::sync::watch::channel(Arc::default()).1,
+        )
+        .await;
+        let api = Api::new(handle.chitchat(), verified.clone(), &config, "");
+        assert!(api.gossip_running());
+        let readiness = api.readiness();
+        assert!(readiness.ready, "{readiness:?}");
+        assert!(readiness.seed_contact);
+
+        // This node alone is not enough
+        config.min_live_nodes = 2;
+        assert!(!Api::new(handle.chitchat(), verified, &config, "").readiness().ready);
+
+        handle
+            .chitchat()
+            .lock()
+            .self_node_state()
+            .set(ZerostateKeys::Proxies.to_string(), r#"[{"socket_address":"a.b.c.d:1"}]"#);
+        let readiness = api.readiness();
+        assert!(!readiness.ready);
+        assert_eq!(readiness.own_state_rejection.unwrap().step, ValidationStep::Proxies);
+    }
+
+    #[tokio::test]
+    async fn metrics_count_revoked_licenses_of_this_node() {
+        let handle =
+            test_chitchat("test", 10000, valid_node_key_values().into_iter().collect()).await;
+        let k_v = valid_node_key_values();
+        let pubkey = &k_v[&ZerostateKeys::Pubkey.to_string()];
+        let signatures: Vec<LicenceSignature> =
+            serde_json::from_str(&k_v[&ZerostateKeys::Signatures.to_string()]).unwrap();
+        let revocations = RevocationList {
+            version: 1,
+            issued_at: 0,
+            licenses: vec![LicenseRevocation {
+                license_id: signatures[0].license_id.clone(),
+                reason: None,
+            }],
+        };
+        let config: Config = serde_yaml::from_str("auto_update: false").unwrap();
+        let verified = VerificationCache::spawn(
+            handle.chitchat(),
+            config.timestamp_policy,
+            Arc::new(BackendKeyring::from_config(&NetworkProfile::devnet(), &[]).unwrap()),
+            tokio::sync::watch::channel(Arc::new(revocations)).1,
+        )
+        .await;
+        verified.subscribe().wait_for(|view| !view.revoked_licenses.is_empty()).await.unwrap();
+
+        // The license is revoked for the node that advertised it, not for its provider
+        let metrics = Api::new(handle.chitchat(), verified.clone(), &config, pubkey).metrics();
+        assert!(metrics.contains("igniter_revoked_licenses 1\n"), "{metrics}");
+        let provider = &signatures[0].provider_pubkey;
+        let metrics = Api::new(handle.chitchat(), verified, &config, provider).metrics();
+        assert!(metrics.contains("igniter_revoked_licenses 0\n"), "{metrics}");
+    }
 
     #[test]
     fn test_validate_licenses_success() {
@@ -0,0 +1,349 @@
+// License revocations issued by the backend. The list of revoked license ids is signed with a
+// backend key valid at the `issued_at` time of the list, see `backend_keys`, so a list stays valid
+// after the key is rotated. The detached signature is stored next to it like for the seeds lists.
+// The list is loaded from `revocations.file` or `revocations.url` and, unless
+// `revocations.gossip` is off, published under the `revocations` gossip key so that the nodes
+// without a source get it too. The valid list with the highest version wins.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::bail;
+use chitchat::ChitchatId;
+use chitchat::ChitchatRef;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::backend_keys::BackendKeyring;
+use crate::config::RevocationsConfig;
+use crate::gossip::set_if_changed;
+use crate::seeds::signature_path;
+use crate::seeds::SIGNATURE_SUFFIX;
+use crate::signature_batch::SignedMessage;
+use crate::ZerostateKeys;
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+// How often the lists of the other nodes are checked
+const GOSSIP_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// Allowed clock skew between the backend and the node
+const MAX_ISSUED_AHEAD_SECS: u64 = 600;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RevocationList {
+    // Increased by the backend with every new list, 0 means no list
+    pub version: u64,
+    // When the backend signed the list, in seconds since the Unix epoch. The signature is checked
+    // with the keys valid at this time.
+    pub issued_at: u64,
+    #[serde(default)]
+    pub licenses: Vec<LicenseRevocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LicenseRevocation {
+    pub license_id: String,
+    // Why the license is revoked, e.g. "refunded"
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl RevocationList {
+    pub fn by_license_id(&self) -> HashMap<&str, &LicenseRevocation> {
+        self.licenses
+            .iter()
+            .map(|revocation| (revocation.license_id.as_str(), revocation))
+            .collect()
+    }
+}
+
+// The list as it was published, the signature covers the exact `body` bytes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedRevocationList {
+    pub body: String,
+    pub signature: String,
+}
+
+impl SignedRevocationList {
+    // `now` is in seconds since the Unix epoch
+    pub fn verify(
+        &self,
+        backend_keys: &BackendKeyring,
+        now: u64,
+    ) -> anyhow::Result<RevocationList> {
+        // `issued_at` is read before the check, a forged value fails it as it is signed too
+        let list: RevocationList = serde_yaml::from_str(&self.body)?;
+        if list.issued_at > now.saturating_add(MAX_ISSUED_AHEAD_SECS) {
+            bail!("revocation list is issued in the future: {}", list.issued_at);
+        }
+        let verified = backend_keys.keys_at(list.issued_at).any(|(_, key)| {
+            SignedMessage::new(key, self.body.as_bytes().to_vec(), self.signature.trim())
+                .is_ok_and(|message| message.verify())
+        });
+        if !verified {
+            bail!("revocation list signature check failed");
+        }
+        Ok(list)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RevocationSource {
+    File(PathBuf),
+    Url(String),
+}
+
+impl RevocationSource {
+    pub fn from_config(config: &RevocationsConfig) -> Option<Self> {
+        match (&config.file, &config.url) {
+            (Some(path), _) => Some(RevocationSource::File(path.clone())),
+            (None, Some(url)) => Some(RevocationSource::Url(url.clone())),
+            (None, None) => None,
+        }
+    }
+
+    pub fn load(&self) -> anyhow::Result<SignedRevocationList> {
+        match self {
+            RevocationSource::File(path) => {
+                let path = PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).into_owned());
+                let body = std::fs::read_to_string(&path)?;
+                let signature = std::fs::read_to_string(signature_path(&path))
+                    .map_err(|err| anyhow::anyhow!("revocation list is not signed: {err}"))?;
+                Ok(SignedRevocationList { body, signature })
+            }
+            RevocationSource::Url(url) => {
+                let client = Client::builder().timeout(DOWNLOAD_TIMEOUT).build()?;
+                let body = client.get(url).send()?.error_for_status()?.text()?;
+                let signature = client
+                    .get(format!("{url}{SIGNATURE_SUFFIX}"))
+                    .send()?
+                    .error_for_status()?
+                    .text()?;
+                Ok(SignedRevocationList { body, signature })
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RevocationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RevocationSource::File(path) => write!(f, "file {path:?}"),
+            RevocationSource::Url(url) => write!(f, "url {url}"),
+        }
+    }
+}
+
+// The active list is sent to the receiver, the task stops when the receiver is dropped.
+// The source is loaded once before returning, so the first verified view already uses it.
+pub async fn spawn(
+    chitchat: ChitchatRef,
+    config: RevocationsConfig,
+    backend_keys: Arc<BackendKeyring>,
+) -> watch::Receiver<Arc<RevocationList>> {
+    let (sender, receiver) = watch::channel(Arc::<RevocationList>::default());
+    let source = RevocationSource::from_config(&config);
+    let mut tracker =
+        RevocationTracker { backend_keys, active: None, gossiped: HashMap::default() };
+    if let Some(source) = &source {
+        tracker.load(source).await;
+        tracker.publish(&chitchat, &sender, config.gossip);
+    }
+    let refresh = Duration::from_secs(config.refresh_secs);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(GOSSIP_CHECK_INTERVAL);
+        let mut next_load = Instant::now() + refresh;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = sender.closed() => return,
+            }
+            if let Some(source) = source.as_ref().filter(|_| Instant::now() >= next_load) {
+                next_load = Instant::now() + refresh;
+                tracker.load(source).await;
+            }
+            if config.gossip {
+                tracker.read_gossip(&chitchat);
+            }
+            tracker.publish(&chitchat, &sender, config.gossip);
+        }
+    });
+    receiver
+}
+
+struct RevocationTracker {
+    backend_keys: Arc<BackendKeyring>,
+    active: Option<(SignedRevocationList, RevocationList)>,
+    // Last seen gossip value of every node, unchanged values are not verified again
+    gossiped: HashMap<ChitchatId, String>,
+}
+
+impl RevocationTracker {
+    async fn load(&mut self, source: &RevocationSource) {
+        let loaded = tokio::task::spawn_blocking({
+            let source = source.clone();
+            move || source.load()
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|loaded| loaded);
+        match loaded {
+            Ok(signed) => self.offer(signed, &source.to_string()),
+            Err(err) => tracing::warn!("Unable to load revocation list from {source}: {err}"),
+        }
+    }
+
+    fn read_gossip(&mut self, chitchat: &ChitchatRef) {
+        let key = ZerostateKeys::Revocations.to_string();
+        let lists: Vec<(ChitchatId, String)> = {
+            let chitchat = chitchat.lock();
+            chitchat
+                .node_states()
+                .iter()
+                .filter(|(id, _)| *id != chitchat.self_chitchat_id())
+                .filter_map(|(id, node_state)| {
+                    Some((id.clone(), node_state.get(&key)?.to_string()))
+                })
+                .collect()
+        };
+        for (id, value) in lists {
+            if self.gossiped.get(&id) == Some(&value) {
+                continue;
+            }
+            self.gossiped.insert(id.clone(), value.clone());
+            match serde_json::from_str(&value) {
+                Ok(signed) => self.offer(signed, &format!("node {}", id.node_id)),
+                Err(err) => tracing::warn!("Invalid revocation list of node {}: {err}", id.node_id),
+            }
+        }
+    }
+
+    // Keeps the list if it is valid and newer than the active one
+    fn offer(&mut self, signed: SignedRevocationList, origin: &str) {
+        match signed.verify(&self.backend_keys, telemetry_utils::now_ms() / 1000) {
+            Ok(list) if list.version > self.version() => {
+                tracing::info!(
+                    "Using revocation list version {} from {origin}: {} revoked licenses",
+                    list.version,
+                    list.licenses.len()
+                );
+                self.active = Some((signed, list));
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!("Revocation list from {origin} is rejected: {err}"),
+        }
+    }
+
+    fn version(&self) -> u64 {
+        self.active.as_ref().map_or(0, |(_, list)| list.version)
+    }
+
+    fn publish(
+        &self,
+        chitchat: &ChitchatRef,
+        sender: &watch::Sender<Arc<RevocationList>>,
+        gossip: bool,
+    ) {
+        let Some((signed, list)) = &self.active else {
+            return;
+        };
+        sender.send_if_modified(|current| {
+            let modified = current.version != list.version;
+            if modified {
+                *current = Arc::new(list.clone());
+            }
+            modified
+        });
+        if gossip {
+            let value = serde_json::to_string(signed).expect("revocation list serialization");
+            set_if_changed(chitchat, &ZerostateKeys::Revocations.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine as _;
+    use ed25519_dalek::Signer;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::config::BackendKey;
+
+    fn signed(signing_key: &SigningKey, version: u64, issued_at: u64) -> SignedRevocationList {
+        let body = format!(
+            "version: {version}\nissued_at: {issued_at}\nlicenses:\n  - license_id: a\n    reason: refunded\n"
+        );
+        let signature = STANDARD.encode(signing_key.sign(body.as_bytes()).to_bytes());
+        SignedRevocationList { body, signature }
+    }
+
+    #[test]
+    fn only_signed_lists_are_accepted() {
+        let backend_key = SigningKey::generate(&mut OsRng);
+        let backend_pk = hex::encode(backend_key.verifying_key().to_bytes());
+        let key = BackendKey { valid_until: Some(2000), ..BackendKey::new("test", &backend_pk) };
+        let backend_keys = BackendKeyring::new(vec![key]).unwrap();
+
+        let list = signed(&backend_key, 3, 1000).verify(&backend_keys, 1000).unwrap();
+        assert_eq!(list.version, 3);
+        assert_eq!(list.issued_at, 1000);
+        assert_eq!(list.by_license_id()["a"].reason.as_deref(), Some("refunded"));
+
+        let mut forged = signed(&backend_key, 3, 1000);
+        forged.body = forged.body.replace("version: 3", "version: 4");
+        assert!(forged.verify(&backend_keys, 1000).is_err());
+
+        let other_key = SigningKey::generate(&mut OsRng);
+        assert!(signed(&other_key, 3, 1000).verify(&backend_keys, 1000).is_err());
+    }
+
+    #[test]
+    fn lists_are_checked_with_the_keys_valid_at_their_issue_time() {
+        let old_key = SigningKey::generate(&mut OsRng);
+        let new_key = SigningKey::generate(&mut OsRng);
+        let old = BackendKey {
+            valid_until: Some(2000),
+            ..BackendKey::new("old", &hex::encode(old_key.verifying_key().to_bytes()))
+        };
+        let new = BackendKey {
+            valid_from: Some(2000),
+            ..BackendKey::new("new", &hex::encode(new_key.verifying_key().to_bytes()))
+        };
+        let backend_keys = BackendKeyring::new(vec![old, new]).unwrap();
+
+        // A list issued before the rotation is still valid after it
+        assert!(signed(&old_key, 3, 1500).verify(&backend_keys, 5000).is_ok());
+        assert!(signed(&new_key, 4, 2500).verify(&backend_keys, 5000).is_ok());
+        // The retired key can't issue new lists, nor the new key old ones
+        assert!(signed(&old_key, 4, 2500).verify(&backend_keys, 5000).is_err());
+        assert!(signed(&new_key, 3, 1500).verify(&backend_keys, 5000).is_err());
+        // A forged issue time breaks the signature
+        let mut forged = signed(&old_key, 4, 2500);
+        forged.body = forged.body.replace("issued_at: 2500", "issued_at: 1500");
+        assert!(forged.verify(&backend_keys, 5000).is_err());
+        // Lists from the future are rejected
+        assert!(signed(&new_key, 4, 6000).verify(&backend_keys, 5000).is_err());
+        assert!(signed(&new_key, 4, 5600).verify(&backend_keys, 5000).is_ok());
+    }
+
+    #[test]
+    fn file_list_requires_a_signature() {
+        let backend_key = SigningKey::generate(&mut OsRng);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revocations.yaml");
+        let list = signed(&backend_key, 1, 1000);
+        std::fs::write(&path, &list.body).unwrap();
+
+        let source = RevocationSource::File(path.clone());
+        assert!(source.load().is_err());
+        std::fs::write(signature_path(&path), &list.signature).unwrap();
+        assert_eq!(source.load().unwrap(), list);
+    }
+}
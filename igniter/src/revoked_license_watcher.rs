@@ -1,20 +1,416 @@
+// Tracks the licenses of this node and reports a license once when it moves between active and
+// revoked. The last known status of every license is kept in `license_watcher.state_file`, so a
+// restart doesn't report the same revocation again. With `license_watcher.drop_revoked` the
+// revoked licenses are removed from the own `signatures` and `licenses` gossip keys. The config
+// reloader writes the same keys, it leaves out the dropped licenses too, see `DroppedLicenses`.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
 use chitchat::ChitchatRef;
+use serde::Deserialize;
+use serde::Serialize;
 use tokio::task::JoinHandle;
 
-use crate::open_api::routes::extract_verified_state_without_licences;
+use crate::cli::Params;
+use crate::config::LicenceSignature;
+use crate::open_api::routes::Licences;
+use crate::utils::RevocationReason;
+use crate::verification_cache::VerificationCache;
+use crate::verification_cache::VerifiedView;
+use crate::VerifiedSignatures;
+use crate::ZerostateKeys;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum LicenseStatus {
+    Active,
+    Revoked,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LicenseRecord {
+    pub status: LicenseStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<RevocationReason>,
+    // Reason given in the backend revocation list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LicenseTransition {
+    pub license_id: String,
+    // None if the license has not been seen before
+    pub previous: Option<LicenseStatus>,
+    pub record: LicenseRecord,
+}
+
+// Last known status of the own licenses by license id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LicenseTracker {
+    records: BTreeMap<String, LicenseRecord>,
+}
+
+impl LicenseTracker {
+    // A missing file means that nothing is known yet
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let path = expand(path);
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Ok(LicenseTracker { records: serde_yaml::from_str(&contents)? }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        // Write to a temporary file first so that a crash never leaves a truncated file
+        let path = expand(path);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_yaml::to_string(&self.records)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    // Licenses missing from the view, e.g. dropped from gossip, keep their last status. A license
+    // seen for the first time is only reported if it is revoked.
+    pub fn update(&mut self, view: &VerifiedView, pubkey: &str) -> Vec<LicenseTransition> {
+        let mut observed = BTreeMap::new();
+        for node in view.verified_state.iter().filter(|node| node.pubkey == pubkey) {
+            for sig in &node.signatures {
+                let record =
+                    LicenseRecord { status: LicenseStatus::Active, reason: None, details: None };
+                observed.insert(sig.license_id.clone(), record);
+            }
+        }
+        for license in
+            view.revoked_licenses.iter().filter(|license| license.provider_pubkey == pubkey)
+        {
+            observed.entry(license.license_id.clone()).or_insert_with(|| LicenseRecord {
+                status: LicenseStatus::Revoked,
+                reason: Some(license.reason),
+                details: license.details.clone(),
+            });
+        }
+
+        let mut transitions = vec![];
+        for (license_id, record) in observed {
+            let previous = self.records.get(&license_id).map(|record| record.status);
+            let reported = match previous {
+                Some(previous) => previous != record.status,
+                None => record.status == LicenseStatus::Revoked,
+            };
+            if reported {
+                transitions.push(LicenseTransition {
+                    license_id: license_id.clone(),
+                    previous,
+                    record: record.clone(),
+                });
+            }
+            self.records.insert(license_id, record);
+        }
+        transitions
+    }
+}
+
+// Delegations of this node that are no longer advertised, by license id and timestamp. It is only
+// changed together with the gossip state under the chitchat lock, so the config reloader and the
+// watcher agree on the published `signatures` and `licenses`.
+#[derive(Debug, Clone, Default)]
+pub struct DroppedLicenses(Arc<Mutex<BTreeSet<(String, u64)>>>);
 
-// This watcher print warn message every minute if some of delegated licenses was revoked
-pub async fn run(chitchat: ChitchatRef, pubkey: String) -> JoinHandle<()> {
+impl DroppedLicenses {
+    // Removes the dropped delegations from the `signatures` and `licenses` values. The caller holds
+    // the chitchat lock until the values are set.
+    pub fn filter(&self, key_values: &mut [(String, String)]) -> anyhow::Result<()> {
+        let dropped = self.0.lock().expect("dropped licenses lock");
+        let signatures_key = ZerostateKeys::Signatures.to_string();
+        let licenses_key = ZerostateKeys::Licenses.to_string();
+        let Some((_, signatures)) = key_values.iter().find(|(key, _)| *key == signatures_key)
+        else {
+            return Ok(());
+        };
+        let Some(filtered) = without_dropped(signatures, &dropped)? else {
+            return Ok(());
+        };
+        for (key, value) in key_values {
+            if *key == signatures_key {
+                value.clone_from(&filtered.signatures);
+            } else if *key == licenses_key {
+                value.clone_from(&filtered.licenses);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn run(
+    verified: VerificationCache,
+    chitchat: ChitchatRef,
+    params: &Params,
+    dropped: DroppedLicenses,
+) -> JoinHandle<()> {
+    let pubkey = params.keys.wallet.pubkey.clone();
+    let config = params.config.license_watcher.clone();
+    let mut tracker = match &config.state_file {
+        Some(path) => LicenseTracker::load(path).unwrap_or_else(|err| {
+            tracing::warn!("Unable to read license state {path:?}, starting from scratch: {err}");
+            LicenseTracker::default()
+        }),
+        None => LicenseTracker::default(),
+    };
+    let mut watcher = verified.subscribe();
     tokio::spawn(async move {
+        // The cache stops with its last clone
+        let _verified = verified;
         loop {
-            let node_states = chitchat.lock().state_snapshot().node_states;
-            let (_, revoked_licenses) = extract_verified_state_without_licences(node_states);
-            for license in revoked_licenses.into_iter() {
-                if license.provider_pubkey == pubkey {
-                    eprintln!("WARNING: Licence with id {} was revoked", license.license_id)
+            let view = watcher.borrow_and_update().clone();
+            let previous = tracker.clone();
+            for transition in tracker.update(&view, &pubkey) {
+                report(&transition);
+            }
+            if let Some(path) = config.state_file.as_ref().filter(|_| tracker != previous) {
+                if let Err(err) = tracker.save(path) {
+                    tracing::warn!("Unable to save license state {path:?}: {err}");
                 }
             }
-            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+            if config.drop_revoked {
+                let revoked = view
+                    .revoked_licenses
+                    .iter()
+                    .filter(|license| license.provider_pubkey == pubkey)
+                    .map(|license| (license.license_id.clone(), license.timestamp))
+                    .collect();
+                match drop_from_gossip(&chitchat, &dropped, revoked) {
+                    Ok(dropped) if dropped.is_empty() => {}
+                    Ok(dropped) => tracing::info!(
+                        "Revoked licenses are no longer advertised: {}",
+                        dropped.join(", ")
+                    ),
+                    Err(err) => tracing::error!("Unable to drop revoked licenses: {err}"),
+                }
+            }
+            if watcher.changed().await.is_err() {
+                return;
+            }
         }
     })
 }
+
+fn report(transition: &LicenseTransition) {
+    let LicenseTransition { license_id, previous, record } = transition;
+    tracing::warn!(
+        license_id,
+        status = %record.status,
+        previous = previous.map(|status| status.to_string()),
+        reason = record.reason.map(|reason| reason.to_string()),
+        details = record.details.as_deref(),
+        "License status changed"
+    );
+    match (record.status, record.reason) {
+        (LicenseStatus::Active, _) => eprintln!("Licence with id {license_id} is active again"),
+        (LicenseStatus::Revoked, Some(RevocationReason::RevokedByBackend)) => eprintln!(
+            "WARNING: Licence with id {license_id} was revoked by the backend: {}",
+            record.details.as_deref().unwrap_or("no reason given")
+        ),
+        (LicenseStatus::Revoked, _) => {
+            eprintln!("WARNING: Licence with id {license_id} was revoked")
+        }
+    }
+}
+
+// Rewrites the own `signatures` and `licenses` keys without the given delegations and keeps them
+// as the dropped ones. The timestamp is matched too, so a license delegated again after the view
+// was built is kept. Returns the ids of the dropped licenses.
+fn drop_from_gossip(
+    chitchat: &ChitchatRef,
+    dropped: &DroppedLicenses,
+    revoked: BTreeSet<(String, u64)>,
+) -> anyhow::Result<Vec<String>> {
+    let signatures_key = ZerostateKeys::Signatures.to_string();
+    let mut chitchat = chitchat.lock();
+    let mut dropped = dropped.0.lock().expect("dropped licenses lock");
+    *dropped = revoked;
+    let node_state = chitchat.self_node_state();
+    let Some(value) = node_state.get(&signatures_key) else {
+        return Ok(vec![]);
+    };
+    let Some(filtered) = without_dropped(value, &dropped)? else {
+        return Ok(vec![]);
+    };
+    node_state.set(signatures_key, filtered.signatures);
+    node_state.set(ZerostateKeys::Licenses.to_string(), filtered.licenses);
+    Ok(filtered.license_ids)
+}
+
+struct Filtered {
+    // Ids of the removed licenses
+    license_ids: Vec<String>,
+    signatures: String,
+    licenses: String,
+}
+
+// The `signatures` and `licenses` values without the dropped delegations, None if none of them is
+// in `signatures`
+fn without_dropped(
+    signatures: &str,
+    dropped: &BTreeSet<(String, u64)>,
+) -> anyhow::Result<Option<Filtered>> {
+    if dropped.is_empty() {
+        return Ok(None);
+    }
+    let (removed, kept): (Vec<LicenceSignature>, Vec<LicenceSignature>) =
+        serde_json::from_str::<Vec<LicenceSignature>>(signatures)?
+            .into_iter()
+            .partition(|sig| dropped.contains(&(sig.license_id.clone(), sig.timestamp)));
+    if removed.is_empty() {
+        return Ok(None);
+    }
+    let kept = VerifiedSignatures::from_published(kept);
+    let licenses = Licences::derive_licences(&kept);
+    Ok(Some(Filtered {
+        license_ids: removed.into_iter().map(|sig| sig.license_id).collect(),
+        signatures: serde_json::to_string(kept.get())?,
+        licenses: serde_json::to_string(&licenses.get())?,
+    }))
+}
+
+fn expand(path: &Path) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::gossip::test_chitchat;
+    use crate::open_api::routes::VerifiedNodeState;
+    use crate::utils::RevokedLicense;
+
+    const PUBKEY: &str = "own";
+
+    fn signature(license_id: &str, owner: &str) -> LicenceSignature {
+        LicenceSignature {
+            license_id: license_id.to_string(),
+            license_owner_pubkey: owner.to_string(),
+            provider_pubkey: String::new(),
+            license_proof_sig: String::new(),
+            delegation_sig: String::new(),
+            delegation_confirm_sig: String::new(),
+            timestamp: 2,
+        }
+    }
+
+    fn view(active: &[&str], revoked: &[(&str, RevocationReason)]) -> VerifiedView {
+        let signatures = active.iter().map(|license_id| signature(license_id, "")).collect();
+        let node = VerifiedNodeState {
+            pubkey: PUBKEY.to_string(),
+            bls_key: String::new(),
+            signatures,
+            licenses: HashMap::new(),
+            version: String::new(),
+        };
+        let revoked_licenses = revoked
+            .iter()
+            .map(|(license_id, reason)| RevokedLicense {
+                license_id: license_id.to_string(),
+                provider_pubkey: PUBKEY.to_string(),
+                timestamp: 1,
+                reason: *reason,
+                details: None,
+            })
+            .collect();
+        VerifiedView { verified_state: vec![node], revoked_licenses, ..Default::default() }
+    }
+
+    fn reported(transitions: &[LicenseTransition]) -> Vec<(&str, LicenseStatus)> {
+        transitions.iter().map(|t| (t.license_id.as_str(), t.record.status)).collect()
+    }
+
+    #[test]
+    fn transitions_are_reported_once_and_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("licenses.yaml");
+        let mut tracker = LicenseTracker::load(&path).unwrap();
+
+        assert!(tracker.update(&view(&["a", "b"], &[]), PUBKEY).is_empty());
+        let revoked = view(&["a"], &[("b", RevocationReason::ReDelegated)]);
+        assert_eq!(reported(&tracker.update(&revoked, PUBKEY)), [("b", LicenseStatus::Revoked)]);
+        assert!(tracker.update(&revoked, PUBKEY).is_empty());
+        // A license missing from the view keeps its status
+        assert!(tracker.update(&view(&["a"], &[]), PUBKEY).is_empty());
+        tracker.save(&path).unwrap();
+
+        let mut tracker = LicenseTracker::load(&path).unwrap();
+        assert!(tracker.update(&revoked, PUBKEY).is_empty());
+        let restored = tracker.update(&view(&["a", "b"], &[]), PUBKEY);
+        assert_eq!(reported(&restored), [("b", LicenseStatus::Active)]);
+        assert_eq!(restored[0].previous, Some(LicenseStatus::Revoked));
+
+        // Licenses of the other nodes are ignored, a new revoked one is reported right away
+        let mut other = view(&[], &[("c", RevocationReason::RevokedByBackend)]);
+        assert_eq!(reported(&tracker.update(&other, PUBKEY)), [("c", LicenseStatus::Revoked)]);
+        other.revoked_licenses[0].provider_pubkey = "other".to_string();
+        assert!(LicenseTracker::default().update(&other, PUBKEY).is_empty());
+    }
+
+    fn gossip(signatures: &[LicenceSignature]) -> Vec<(String, String)> {
+        let signatures = VerifiedSignatures::from_published(signatures.to_vec());
+        vec![
+            (
+                ZerostateKeys::Signatures.to_string(),
+                serde_json::to_string(signatures.get()).unwrap(),
+            ),
+            (
+                ZerostateKeys::Licenses.to_string(),
+                serde_json::to_string(&Licences::derive_licences(&signatures).get()).unwrap(),
+            ),
+        ]
+    }
+
+    // `licenses` is serialized from a HashMap, so it is compared parsed
+    fn parsed(key_values: &[(String, String)]) -> (&str, HashMap<String, i32>) {
+        (&key_values[0].1, serde_json::from_str(&key_values[1].1).unwrap())
+    }
+
+    #[tokio::test]
+    async fn dropped_licenses_stay_out_of_gossip() {
+        let all = [signature("a", "owner1"), signature("b", "owner1"), signature("c", "owner2")];
+        let handle = test_chitchat("test", 10000, gossip(&all)).await;
+        let chitchat = handle.chitchat();
+        let published = || {
+            let mut chitchat = chitchat.lock();
+            let node_state = chitchat.self_node_state();
+            [ZerostateKeys::Signatures, ZerostateKeys::Licenses]
+                .map(|key| (key.to_string(), node_state.get(&key.to_string()).unwrap().to_string()))
+                .to_vec()
+        };
+        let dropped = DroppedLicenses::default();
+
+        // The timestamp must match, a newer delegation of the license is kept
+        let revoked = BTreeSet::from([("a".to_string(), 1)]);
+        assert!(drop_from_gossip(&chitchat, &dropped, revoked).unwrap().is_empty());
+        assert_eq!(parsed(&published()), parsed(&gossip(&all)));
+
+        let revoked = BTreeSet::from([("a".to_string(), 2), ("c".to_string(), 2)]);
+        assert_eq!(drop_from_gossip(&chitchat, &dropped, revoked.clone()).unwrap(), ["a", "c"]);
+        let kept = gossip(&all[1..2]);
+        assert_eq!(parsed(&published()), parsed(&kept));
+        assert!(drop_from_gossip(&chitchat, &dropped, revoked).unwrap().is_empty());
+
+        // The config reloader publishes the same values as long as the licenses stay revoked
+        let mut reloaded = gossip(&all);
+        dropped.filter(&mut reloaded).unwrap();
+        assert_eq!(parsed(&reloaded), parsed(&kept));
+        assert!(drop_from_gossip(&chitchat, &dropped, BTreeSet::new()).unwrap().is_empty());
+        let mut reloaded = gossip(&all);
+        dropped.filter(&mut reloaded).unwrap();
+        assert_eq!(parsed(&reloaded), parsed(&gossip(&all)));
+    }
+}
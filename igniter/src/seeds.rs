@@ -0,0 +1,391 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use reqwest::blocking::Client;
+use tvm_types::ed25519_verify;
+
+use crate::config::Config;
+use crate::network::NetworkProfile;
+
+pub const SEEDS_DOWNLOAD_ATTEMPTS: u32 = 3;
+pub const SEEDS_DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+const SEEDS_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+// Detached signature of a seeds list is stored next to it: `seeds.yaml.sig`
+pub(crate) const SIGNATURE_SUFFIX: &str = ".sig";
+
+#[derive(Debug, Clone)]
+pub enum SeedSource {
+    // `seeds` section of config.yaml
+    Inline(Vec<String>),
+    // Local file in the seeds.yaml format
+    File(PathBuf),
+    // `github_auth` adds GITHUB_TOKEN to the request, it is only set for the network seeds URL
+    Url { url: String, github_auth: bool },
+    // Seeds from the last successful download
+    Cache(PathBuf),
+}
+
+impl SeedSource {
+    // Lists from config.yaml and a local file are provided by the operator and trusted as is
+    fn requires_signature(&self) -> bool {
+        matches!(self, SeedSource::Url { .. } | SeedSource::Cache(_))
+    }
+}
+
+impl std::fmt::Display for SeedSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeedSource::Inline(_) => write!(f, "config seeds"),
+            SeedSource::File(path) => write!(f, "file {path:?}"),
+            SeedSource::Url { url, .. } => write!(f, "url {url}"),
+            SeedSource::Cache(path) => write!(f, "cache {path:?}"),
+        }
+    }
+}
+
+// Seeds list as it was received, the signature covers the exact `body` bytes
+struct RawSeeds {
+    body: String,
+    signature: Option<String>,
+}
+
+// Sources are tried in order, the first one that returns a non-empty list wins
+#[derive(Debug, Clone)]
+pub struct SeedSources {
+    pub sources: Vec<SeedSource>,
+    pub cache: Option<PathBuf>,
+    pub attempts: u32,
+    pub retry_delay: Duration,
+    // Downloaded and cached lists must be signed with this key, without it they are not checked
+    pub verifying_key: Option<[u8; 32]>,
+    // Downloads with `github_auth` fail without GITHUB_TOKEN
+    pub github_token_required: bool,
+}
+
+impl SeedSources {
+    pub fn from_config(config: &Config, network: &NetworkProfile) -> anyhow::Result<Self> {
+        let mut sources = vec![SeedSource::Inline(config.seeds.clone())];
+        if let Some(path) = &config.seeds_file {
+            sources.push(SeedSource::File(path.clone()));
+        }
+        if let Some(url) = &network.seeds_url {
+            sources.push(SeedSource::Url { url: url.clone(), github_auth: true });
+        }
+        for url in config.seeds_mirrors.iter().chain(&network.seeds_mirrors) {
+            sources.push(SeedSource::Url { url: url.clone(), github_auth: false });
+        }
+        if let Some(path) = &config.seeds_cache {
+            sources.push(SeedSource::Cache(path.clone()));
+        }
+        let verifying_key =
+            match config.seeds_verifying_key.as_ref().or(network.seeds_verifying_key.as_ref()) {
+                Some(key) => Some(
+                    hex::decode(key)?
+                        .try_into()
+                        .map_err(|_| anyhow!("seeds_verifying_key must be 32 bytes long"))?,
+                ),
+                None => None,
+            };
+        Ok(SeedSources {
+            sources,
+            cache: config.seeds_cache.clone(),
+            attempts: SEEDS_DOWNLOAD_ATTEMPTS,
+            retry_delay: SEEDS_DOWNLOAD_RETRY_DELAY,
+            verifying_key,
+            github_token_required: network.github_token_required,
+        })
+    }
+
+    pub fn load(&self) -> anyhow::Result<Vec<String>> {
+        let mut failures = vec![];
+        for source in &self.sources {
+            let seeds = match source {
+                SeedSource::Inline(seeds) => Ok(seeds.clone()),
+                _ => self
+                    .load_raw(source)
+                    .and_then(|raw| {
+                        self.verify(source, &raw)?;
+                        let seeds: Vec<String> = serde_yaml::from_str(&raw.body)?;
+                        Ok((seeds, raw))
+                    })
+                    .map(|(seeds, raw)| {
+                        if let (SeedSource::Url { .. }, Some(cache), false) =
+                            (source, &self.cache, seeds.is_empty())
+                        {
+                            if let Err(error) = write_cache(cache, &raw) {
+                                eprintln!("Unable to update seeds cache {cache:?}: {error}");
+                            }
+                        }
+                        seeds
+                    }),
+            };
+            match seeds {
+                Ok(seeds) if !seeds.is_empty() => {
+                    eprintln!("Using {} seeds from {source}", seeds.len());
+                    return Ok(seeds);
+                }
+                Ok(_) => failures.push(format!("{source}: empty")),
+                Err(error) => {
+                    eprintln!("Unable to get seeds from {source}: {error}");
+                    failures.push(format!("{source}: {error}"));
+                }
+            }
+        }
+        bail!("all seed sources failed: {}", failures.join("; "))
+    }
+
+    fn verify(&self, source: &SeedSource, raw: &RawSeeds) -> anyhow::Result<()> {
+        let Some(verifying_key) = self.verifying_key.filter(|_| source.requires_signature()) else {
+            return Ok(());
+        };
+        let Some(signature) = &raw.signature else {
+            bail!("seeds list is not signed");
+        };
+        let signature = STANDARD
+            .decode(signature.trim())
+            .map_err(|_| anyhow!("seeds list signature check failed"))?;
+        ed25519_verify(&verifying_key, raw.body.as_bytes(), &signature)
+            .map_err(|_| anyhow!("seeds list signature check failed"))
+    }
+
+    fn load_raw(&self, source: &SeedSource) -> anyhow::Result<RawSeeds> {
+        match source {
+            SeedSource::Inline(seeds) => {
+                Ok(RawSeeds { body: serde_yaml::to_string(seeds)?, signature: None })
+            }
+            SeedSource::File(path) | SeedSource::Cache(path) => read_raw(path),
+            SeedSource::Url { url, github_auth } => {
+                let mut delay = self.retry_delay;
+                let mut attempt = 1;
+                loop {
+                    match self.download(url, *github_auth) {
+                        Ok(raw) => return Ok(raw),
+                        Err(error) if attempt < self.attempts => {
+                            eprintln!(
+                                "Attempt {attempt} to download seeds from {url} failed: {error}"
+                            );
+                            std::thread::sleep(delay);
+                            delay *= 2;
+                            attempt += 1;
+                        }
+                        Err(error) => return Err(error),
+                    }
+                }
+            }
+        }
+    }
+
+    fn download(&self, url: &str, github_auth: bool) -> anyhow::Result<RawSeeds> {
+        let client = Client::builder().timeout(SEEDS_DOWNLOAD_TIMEOUT).build()?;
+        let get = |url: &str| -> anyhow::Result<reqwest::blocking::Response> {
+            let mut request = client.get(url);
+            if github_auth {
+                if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+                    request = request.bearer_auth(token);
+                } else if self.github_token_required {
+                    bail!("GITHUB_TOKEN required")
+                }
+            }
+            Ok(request.send()?)
+        };
+        let body = get(url)?.error_for_status()?.text()?;
+        let signature = if self.verifying_key.is_none() {
+            None
+        } else {
+            let response = get(&format!("{url}{SIGNATURE_SUFFIX}"))?;
+            match response.status() {
+                reqwest::StatusCode::NOT_FOUND => None,
+                _ => Some(response.error_for_status()?.text()?),
+            }
+        };
+        Ok(RawSeeds { body, signature })
+    }
+}
+
+pub(crate) fn signature_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(SIGNATURE_SUFFIX);
+    PathBuf::from(path)
+}
+
+fn read_raw(path: &Path) -> anyhow::Result<RawSeeds> {
+    let path = PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).into_owned());
+    let body = std::fs::read_to_string(&path)?;
+    let signature = std::fs::read_to_string(signature_path(&path)).ok();
+    Ok(RawSeeds { body, signature })
+}
+
+fn write_cache(path: &Path, raw: &RawSeeds) -> anyhow::Result<()> {
+    // Both files are written in full before either replaces the cache, so that a crash never
+    // leaves a truncated list or signature
+    let tmp_path = |path: &Path| {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".tmp");
+        PathBuf::from(path)
+    };
+    let signature_path = signature_path(path);
+    std::fs::write(tmp_path(path), &raw.body)?;
+    if let Some(signature) = &raw.signature {
+        std::fs::write(tmp_path(&signature_path), signature)?;
+        std::fs::rename(tmp_path(&signature_path), &signature_path)?;
+    } else {
+        let _ = std::fs::remove_file(&signature_path);
+    }
+    std::fs::rename(tmp_path(path), path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use ed25519_dalek::Signer;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    // Serves `responses` one per connection and returns the base url
+    fn serve(responses: Vec<(u16, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::Builder::new()
+            .name("seeds_http_stub".to_string())
+            .spawn(move || {
+                for (status, body) in responses {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut buf = [0u8; 4096];
+                    let _ = stream.read(&mut buf).unwrap();
+                    let response = format!(
+                        "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    stream.write_all(response.as_bytes()).unwrap();
+                }
+            })
+            .unwrap();
+        format!("http://{addr}/seeds.yaml")
+    }
+
+    fn ok(body: &str) -> (u16, String) {
+        (200, body.to_string())
+    }
+
+    fn err(status: u16) -> (u16, String) {
+        (status, String::new())
+    }
+
+    fn url(url: String) -> SeedSource {
+        SeedSource::Url { url, github_auth: false }
+    }
+
+    fn sources(sources: Vec<SeedSource>, cache: Option<PathBuf>) -> SeedSources {
+        SeedSources {
+            sources,
+            cache,
+            attempts: 2,
+            retry_delay: Duration::from_millis(1),
+            verifying_key: None,
+            github_token_required: false,
+        }
+    }
+
+    fn sign(key: &SigningKey, body: &str) -> String {
+        STANDARD.encode(key.sign(body.as_bytes()).to_bytes())
+    }
+
+    #[test]
+    fn inline_seeds_win() {
+        let seeds = sources(vec![SeedSource::Inline(vec!["127.0.0.1:10000".to_string()])], None)
+            .load()
+            .unwrap();
+        assert_eq!(seeds, vec!["127.0.0.1:10000"]);
+    }
+
+    #[test]
+    fn falls_back_to_mirror_and_updates_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("seeds.yaml");
+        let broken = serve(vec![err(500), err(404)]);
+        let retried = serve(vec![err(503), ok("- \"10.0.0.1:10000\"\n")]);
+
+        let seeds = sources(
+            vec![SeedSource::Inline(vec![]), url(broken), url(retried)],
+            Some(cache.clone()),
+        )
+        .load()
+        .unwrap();
+
+        assert_eq!(seeds, vec!["10.0.0.1:10000"]);
+        assert_eq!(read_raw(&cache).unwrap().body, "- \"10.0.0.1:10000\"\n");
+    }
+
+    #[test]
+    fn uses_cache_when_downloads_fail() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("seeds.yaml");
+        std::fs::write(&cache, "- 10.0.0.2:10000\n").unwrap();
+        let broken = serve(vec![err(500), err(500)]);
+
+        let seeds =
+            sources(vec![url(broken), SeedSource::Cache(cache.clone())], Some(cache)).load();
+        assert_eq!(seeds.unwrap(), vec!["10.0.0.2:10000"]);
+    }
+
+    #[test]
+    fn fails_when_all_sources_are_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = sources(
+            vec![SeedSource::Inline(vec![]), SeedSource::File(dir.path().join("missing.yaml"))],
+            None,
+        )
+        .load();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn only_signed_lists_are_accepted() {
+        let authority = SigningKey::generate(&mut OsRng);
+        let rogue = SigningKey::generate(&mut OsRng);
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("seeds.yaml");
+
+        let rogue_body = "- 6.6.6.6:10000\n";
+        let body = "- 10.0.0.3:10000\n";
+        let unsigned = serve(vec![ok(rogue_body), err(404)]);
+        let badly_signed = serve(vec![ok(rogue_body), ok(&sign(&rogue, rogue_body))]);
+        let signed = serve(vec![ok(body), ok(&sign(&authority, body))]);
+
+        let mut seed_sources =
+            sources(vec![url(unsigned), url(badly_signed), url(signed)], Some(cache.clone()));
+        seed_sources.verifying_key = Some(authority.verifying_key().to_bytes());
+        assert_eq!(seed_sources.load().unwrap(), vec!["10.0.0.3:10000"]);
+        assert!(!cache.with_extension("yaml.tmp").exists());
+
+        // The signature is cached together with the list
+        seed_sources.sources = vec![SeedSource::Cache(cache.clone())];
+        assert_eq!(seed_sources.load().unwrap(), vec!["10.0.0.3:10000"]);
+
+        std::fs::write(&cache, rogue_body).unwrap();
+        assert!(seed_sources.load().is_err());
+    }
+
+    #[test]
+    fn signature_is_checked_only_with_verifying_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("seeds.yaml");
+        std::fs::write(&cache, "- 10.0.0.4:10000\n").unwrap();
+        let mut seed_sources = sources(vec![SeedSource::Cache(cache)], None);
+        assert_eq!(seed_sources.load().unwrap(), vec!["10.0.0.4:10000"]);
+        seed_sources.verifying_key =
+            Some(SigningKey::generate(&mut OsRng).verifying_key().to_bytes());
+        assert!(seed_sources.load().is_err());
+    }
+}
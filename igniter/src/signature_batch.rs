@@ -0,0 +1,126 @@
+// Verification of many ed25519 signatures at once. The signatures are split into batches that
+// are verified on all cores; a batch that fails is re-checked signature by signature, so the
+// invalid ones can still be reported.
+//
+// A signature is valid if `tvm_types::ed25519_verify` accepts it, as in the older igniters. The
+// batch equation is not cofactored and its weights are random, so weak keys and small order R
+// points could be accepted by one batch and rejected by another. Such signatures are never
+// batched, and a batch only accepts: whatever it rejects gets the single checks.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ed25519_dalek::Signature;
+use ed25519_dalek::VerifyingKey;
+use rayon::prelude::*;
+use tvm_types::ed25519_verify;
+
+const BATCH_SIZE: usize = 64;
+
+// A message with its signature and the key to check it with
+pub(crate) struct SignedMessage {
+    pubkey: [u8; 32],
+    message: Vec<u8>,
+    signature: [u8; 64],
+    // Parsed key and signature unless one of them is of small order
+    batchable: Option<(VerifyingKey, Signature)>,
+}
+
+impl SignedMessage {
+    pub(crate) fn new(pubkey: &[u8], message: Vec<u8>, signature: &str) -> anyhow::Result<Self> {
+        let pubkey: [u8; 32] = pubkey.try_into()?;
+        let key = VerifyingKey::from_bytes(&pubkey)?;
+        let signature = Signature::from_slice(&STANDARD.decode(signature)?)?;
+        let batchable =
+            (!key.is_weak() && !is_small_order(signature.r_bytes())).then_some((key, signature));
+        Ok(SignedMessage { pubkey, message, signature: signature.to_bytes(), batchable })
+    }
+
+    pub(crate) fn verify(&self) -> bool {
+        ed25519_verify(&self.pubkey, &self.message, &self.signature).is_ok()
+    }
+}
+
+// Result of every message, in the same order
+pub(crate) fn verify_all(messages: &[SignedMessage]) -> Vec<bool> {
+    messages
+        .par_chunks(BATCH_SIZE)
+        .flat_map_iter(|batch| {
+            if verify_batch(batch) {
+                vec![true; batch.len()]
+            } else {
+                batch.iter().map(SignedMessage::verify).collect()
+            }
+        })
+        .collect()
+}
+
+// False if any message can't be batched
+fn verify_batch(batch: &[SignedMessage]) -> bool {
+    let Some(parsed) = batch.iter().map(|signed| signed.batchable).collect::<Option<Vec<_>>>()
+    else {
+        return false;
+    };
+    let messages: Vec<&[u8]> = batch.iter().map(|signed| signed.message.as_slice()).collect();
+    let (keys, signatures): (Vec<VerifyingKey>, Vec<Signature>) = parsed.into_iter().unzip();
+    ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok()
+}
+
+// Points that can't be decompressed are treated as small order, the single check rejects them
+fn is_small_order(point: &[u8; 32]) -> bool {
+    CompressedEdwardsY(*point).decompress().is_none_or(|point| point.is_small_order())
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::Signer;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    fn signed(signing_key: &SigningKey, message: &str) -> SignedMessage {
+        let signature = STANDARD.encode(signing_key.sign(message.as_bytes()).to_bytes());
+        let pubkey = signing_key.verifying_key().to_bytes();
+        SignedMessage::new(&pubkey, message.as_bytes().to_vec(), &signature).unwrap()
+    }
+
+    #[test]
+    fn failed_batch_reports_the_invalid_signatures() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let mut messages: Vec<SignedMessage> =
+            (0..BATCH_SIZE * 2 + 1).map(|i| signed(&signing_key, &i.to_string())).collect();
+        assert!(verify_all(&messages).iter().all(|valid| *valid));
+
+        messages[3].message = b"forged".to_vec();
+        messages[BATCH_SIZE * 2].message = b"forged".to_vec();
+        let invalid: Vec<usize> = verify_all(&messages)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, valid)| (!valid).then_some(i))
+            .collect();
+        assert_eq!(invalid, [3, BATCH_SIZE * 2]);
+        assert!(!messages[3].verify());
+        assert!(messages[4].verify());
+    }
+
+    #[test]
+    fn small_order_components_are_checked_one_by_one() {
+        // The identity as key and R with a zero S satisfies the single equation for any message
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&identity);
+        let weak = SignedMessage::new(&identity, b"message".to_vec(), &STANDARD.encode(signature))
+            .unwrap();
+        assert!(weak.batchable.is_none());
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let mut messages: Vec<SignedMessage> =
+            (0..BATCH_SIZE - 1).map(|i| signed(&signing_key, &i.to_string())).collect();
+        assert!(messages.iter().all(|signed| signed.batchable.is_some()));
+        messages.push(weak);
+        let expected: Vec<bool> = messages.iter().map(SignedMessage::verify).collect();
+        assert_eq!(verify_all(&messages), expected);
+    }
+}
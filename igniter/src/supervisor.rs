@@ -0,0 +1,479 @@
+// Runs several igniter identities in one process. Every instance has its own
+// config.yaml and keys.yaml and is restarted independently when it fails.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use chitchat::ChitchatRef;
+use poem::listener::TcpListener;
+use poem::middleware::Cors;
+use poem::EndpointExt;
+use poem::Route;
+use poem::Server;
+use poem_openapi::payload::Json;
+use poem_openapi::Enum;
+use poem_openapi::Object;
+use poem_openapi::OpenApi;
+use poem_openapi::OpenApiService;
+use serde::Serialize;
+use updater::ContainerUpdater;
+use updater::DEFAULT_UPDATE_INTERVAL;
+
+use crate::cli::NetworkArgs;
+use crate::cli::Params;
+use crate::cli::SuperviseArgs;
+use crate::config::Config;
+use crate::config_reloader::ConfigReloader;
+use crate::gossip;
+use crate::keys_source::KeysSource;
+use crate::revoked_license_watcher;
+use crate::revoked_license_watcher::DroppedLicenses;
+use crate::utils::init_tracing;
+
+pub const INSTANCE_CONFIG_FILE: &str = "config.yaml";
+pub const INSTANCE_KEYS_FILE: &str = "keys.yaml";
+
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum InstanceState {
+    Starting,
+    Running,
+    Restarting,
+}
+
+#[derive(Debug, Clone, Serialize, Object)]
+pub struct InstanceStatus {
+    pub name: String,
+    pub state: InstanceState,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub advertise_addr: String,
+    pub listen_addr: String,
+    pub api_addr: String,
+    pub live_nodes: usize,
+}
+
+impl InstanceStatus {
+    fn set_addresses(&mut self, config: &Config) {
+        self.advertise_addr = config.advertise_addr.to_string();
+        self.listen_addr = config.listen_addr.to_string();
+        self.api_addr = config.api_addr.to_string();
+    }
+}
+
+struct Instance {
+    name: String,
+    dir: PathBuf,
+    status: InstanceStatus,
+    // Config the instance was last started with, its addresses are taken by the instance
+    config: Config,
+    chitchat: Option<ChitchatRef>,
+}
+
+type Instances = Arc<Mutex<Vec<Instance>>>;
+
+pub fn run(
+    args: &SuperviseArgs,
+    network: &NetworkArgs,
+    docker_socket: Option<String>,
+    docker_config: Option<String>,
+) -> anyhow::Result<()> {
+    let dirs = discover(&args.dir)?;
+    // Fail fast on broken files, later failures only restart the affected instance
+    let mut instances = vec![];
+    let mut params = vec![];
+    for (name, dir) in dirs {
+        let instance_params =
+            load_params(&dir, network, docker_socket.clone(), docker_config.clone())
+                .map_err(|err| anyhow!("Instance {name}: {err}"))?;
+        let mut status = InstanceStatus {
+            name: name.clone(),
+            state: InstanceState::Starting,
+            restarts: 0,
+            last_error: None,
+            advertise_addr: String::new(),
+            listen_addr: String::new(),
+            api_addr: String::new(),
+            live_nodes: 0,
+        };
+        status.set_addresses(&instance_params.config);
+        let config = instance_params.config.clone();
+        instances.push(Instance { status, name, dir, config, chitchat: None });
+        params.push(instance_params);
+    }
+    let configs: Vec<(&str, &Config)> =
+        instances.iter().map(|instance| (instance.name.as_str(), &instance.config)).collect();
+    check_addresses(&configs, args.status_addr)?;
+    // One updater serves all instances, so they have to follow the same image
+    let images: BTreeSet<&str> = params
+        .iter()
+        .filter(|params| params.config.auto_update)
+        .filter_map(|params| params.network.image.as_deref())
+        .collect();
+    let image = match images.into_iter().collect::<Vec<_>>().as_slice() {
+        [] => None,
+        [image] => Some(image.to_string()),
+        images => bail!("Auto updated instances follow different images: {}", images.join(", ")),
+    };
+
+    eprintln!("Supervising {} igniter instances from {:?}", instances.len(), args.dir);
+    let instances: Instances = Arc::new(Mutex::new(instances));
+    let status_addr = args.status_addr;
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_stack_size(16 * 1024 * 1024)
+        .build()?
+        .block_on(async move {
+            init_tracing();
+
+            let updater_handle = tokio::spawn(async move {
+                if let Some(image) = image {
+                    tracing::info!("Auto update enabled");
+                    let updater = ContainerUpdater::try_new(
+                        image,
+                        DEFAULT_UPDATE_INTERVAL,
+                        docker_socket,
+                        docker_config,
+                    )
+                    .await?;
+                    updater.run().await
+                } else {
+                    tracing::info!("Auto update disabled");
+                    std::future::pending().await
+                }
+            });
+
+            for (index, params) in params.into_iter().enumerate() {
+                tokio::spawn(supervise_instance(
+                    instances.clone(),
+                    index,
+                    params,
+                    network.clone(),
+                    status_addr,
+                ));
+            }
+
+            let api = OpenApiService::new(
+                SupervisorApi { instances },
+                env!("CARGO_PKG_DESCRIPTION"),
+                env!("CARGO_PKG_VERSION"),
+            );
+            let docs = api.swagger_ui();
+            let app = Route::new().nest("/", api).nest("/docs", docs).with(Cors::new());
+            tracing::info!("Starting supervisor status API on {status_addr}");
+            let status_handle =
+                tokio::spawn(
+                    async move { Server::new(TcpListener::bind(status_addr)).run(app).await },
+                );
+
+            tokio::select! {
+                v = updater_handle => bail!("Container updater failed: {v:?}"),
+                v = status_handle => bail!("Status API server failed: {v:?}"),
+            }
+        })
+}
+
+// Every subdirectory with both config.yaml and keys.yaml is an instance named after it
+pub fn discover(dir: &Path) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut instances = vec![];
+    for entry in std::fs::read_dir(dir).map_err(|err| anyhow!("Can't read {dir:?}: {err}"))? {
+        let path = entry?.path();
+        if path.join(INSTANCE_CONFIG_FILE).is_file() && path.join(INSTANCE_KEYS_FILE).is_file() {
+            let name = path.file_name().expect("read_dir entries have names");
+            instances.push((name.to_string_lossy().into_owned(), path));
+        }
+    }
+    if instances.is_empty() {
+        bail!(
+            "No instances found in {dir:?}, expected subdirectories with {INSTANCE_CONFIG_FILE} and {INSTANCE_KEYS_FILE}"
+        );
+    }
+    instances.sort();
+    Ok(instances)
+}
+
+fn load_params(
+    dir: &Path,
+    network: &NetworkArgs,
+    docker_socket: Option<String>,
+    docker_config: Option<String>,
+) -> anyhow::Result<Params> {
+    Params::load(
+        &dir.join(INSTANCE_CONFIG_FILE),
+        &KeysSource::file(&dir.join(INSTANCE_KEYS_FILE)),
+        network,
+        docker_socket,
+        docker_config,
+    )
+}
+
+// Instances share the host, so no two of them may bind the same port or advertise the same address
+fn check_addresses(instances: &[(&str, &Config)], status_addr: SocketAddr) -> anyhow::Result<()> {
+    let mut tcp_ports = HashMap::from([(status_addr.port().to_string(), "supervisor")]);
+    let mut udp_ports = HashMap::new();
+    let mut advertised = HashMap::new();
+    for (name, config) in instances {
+        for (used, key, kind) in [
+            (&mut tcp_ports, config.api_addr.port().to_string(), "api_addr port"),
+            (&mut udp_ports, config.listen_addr.port().to_string(), "listen_addr port"),
+            (&mut advertised, config.advertise_addr.to_string(), "advertise_addr"),
+        ] {
+            if let Some(other) = used.insert(key.clone(), *name) {
+                bail!("Instance {name}: {kind} {key} is already used by {other}");
+            }
+        }
+    }
+    Ok(())
+}
+
+// The config of a restarted instance may have changed, it is checked against the addresses the
+// other instances use now
+fn check_restart_addresses(
+    instances: &[Instance],
+    index: usize,
+    config: &Config,
+    status_addr: SocketAddr,
+) -> anyhow::Result<()> {
+    let mut configs: Vec<(&str, &Config)> = instances
+        .iter()
+        .enumerate()
+        .filter(|(other, _)| *other != index)
+        .map(|(_, instance)| (instance.name.as_str(), &instance.config))
+        .collect();
+    configs.push((&instances[index].name, config));
+    check_addresses(&configs, status_addr)
+}
+
+async fn supervise_instance(
+    instances: Instances,
+    index: usize,
+    params: Params,
+    network: NetworkArgs,
+    status_addr: SocketAddr,
+) {
+    let (name, dir, docker_socket, docker_config) = {
+        let instances = instances.lock().unwrap();
+        let instance = &instances[index];
+        (
+            instance.name.clone(),
+            instance.dir.clone(),
+            params.docker_socket.clone(),
+            params.docker_config.clone(),
+        )
+    };
+    let mut params = Some(params);
+    let mut delay = MIN_RESTART_DELAY;
+    loop {
+        let started = Instant::now();
+        let params = match params.take() {
+            Some(params) => Ok(params),
+            // Files are re-read on restart, so a fixed config is picked up without restarting the process
+            None => {
+                let (dir, network) = (dir.clone(), network.clone());
+                let (docker_socket, docker_config) = (docker_socket.clone(), docker_config.clone());
+                tokio::task::spawn_blocking(move || {
+                    load_params(&dir, &network, docker_socket, docker_config)
+                })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result)
+                .and_then(|params| {
+                    let instances = instances.lock().unwrap();
+                    check_restart_addresses(&instances, index, &params.config, status_addr)?;
+                    Ok(params)
+                })
+            }
+        };
+        let error = match params {
+            Ok(params) => run_instance(&instances, index, &dir, params).await,
+            Err(err) => err,
+        };
+
+        if started.elapsed() > MAX_RESTART_DELAY {
+            delay = MIN_RESTART_DELAY;
+        }
+        tracing::error!("Instance {name} failed, restarting in {delay:?}: {error:#}");
+        {
+            let mut instances = instances.lock().unwrap();
+            let instance = &mut instances[index];
+            instance.status.state = InstanceState::Restarting;
+            instance.status.restarts += 1;
+            instance.status.last_error = Some(format!("{error:#}"));
+            instance.chitchat = None;
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RESTART_DELAY);
+    }
+}
+
+// Runs the instance until one of its services stops and returns the reason
+async fn run_instance(
+    instances: &Instances,
+    index: usize,
+    dir: &Path,
+    params: Params,
+) -> anyhow::Error {
+    let (chitchat, mut gossip_handle, mut rest_handle, verified) =
+        match gossip::run(&params, chitchat::transport::UdpTransport).await {
+            Ok(handles) => handles,
+            Err(err) => return err,
+        };
+    let dropped = DroppedLicenses::default();
+    let mut watcher =
+        revoked_license_watcher::run(verified, chitchat.clone(), &params, dropped.clone()).await;
+    let reloader = ConfigReloader::new(
+        chitchat.clone(),
+        params.clone(),
+        dir.join(INSTANCE_CONFIG_FILE),
+        dropped,
+    )
+    .and_then(ConfigReloader::run);
+    let mut reloader = match reloader {
+        Ok(reloader) => reloader,
+        Err(err) => {
+            gossip_handle.abort();
+            rest_handle.abort();
+            watcher.abort();
+            return err;
+        }
+    };
+    {
+        let mut instances = instances.lock().unwrap();
+        let instance = &mut instances[index];
+        instance.status.state = InstanceState::Running;
+        instance.status.set_addresses(&params.config);
+        instance.config = params.config.clone();
+        instance.chitchat = Some(chitchat);
+    }
+
+    let error = tokio::select! {
+        v = &mut gossip_handle.join_handle => anyhow!("Gossip server failed: {v:?}"),
+        v = &mut rest_handle => anyhow!("API server failed: {v:?}"),
+        v = &mut watcher => anyhow!("License watcher failed: {v:?}"),
+        v = &mut reloader => anyhow!("Config reloader failed: {v:?}"),
+    };
+    // Release the ports before the instance is started again
+    gossip_handle.abort();
+    rest_handle.abort();
+    watcher.abort();
+    reloader.abort();
+    error
+}
+
+pub struct SupervisorApi {
+    instances: Instances,
+}
+
+#[OpenApi]
+impl SupervisorApi {
+    /// Status of all supervised instances
+    #[oai(path = "/status", method = "get")]
+    async fn status(&self) -> Json<Vec<InstanceStatus>> {
+        let statuses: Vec<InstanceStatus> = self
+            .instances
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|instance| {
+                let mut status = instance.status.clone();
+                if let Some(chitchat) = &instance.chitchat {
+                    status.live_nodes = chitchat.lock().live_nodes().count();
+                }
+                status
+            })
+            .collect();
+        Json(statuses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_yaml;
+
+    fn config(port: u16) -> Config {
+        let addr = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let mut config = read_yaml::<Config>("./tests/config.yaml").unwrap();
+        config.advertise_addr = addr(port);
+        config.listen_addr = addr(port);
+        config.api_addr = addr(port + 1);
+        config
+    }
+
+    #[test]
+    fn instances_are_discovered_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["node2", "node1", "incomplete"] {
+            std::fs::create_dir(dir.path().join(name)).unwrap();
+            std::fs::write(dir.path().join(name).join(INSTANCE_CONFIG_FILE), "").unwrap();
+        }
+        for name in ["node2", "node1"] {
+            std::fs::write(dir.path().join(name).join(INSTANCE_KEYS_FILE), "").unwrap();
+        }
+
+        let names: Vec<String> =
+            discover(dir.path()).unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["node1", "node2"]);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(discover(empty.path()).is_err());
+    }
+
+    #[test]
+    fn instances_can_not_share_addresses() {
+        let status_addr = SocketAddr::from(([0, 0, 0, 0], 10100));
+        let (a, b) = (config(10000), config(10010));
+        assert!(check_addresses(&[("a", &a), ("b", &b)], status_addr).is_ok());
+
+        let err = check_addresses(&[("a", &config(10099))], status_addr).unwrap_err();
+        assert!(err.to_string().contains("already used by supervisor"));
+        let err = check_addresses(&[("a", &a), ("b", &a)], status_addr);
+        assert!(err.unwrap_err().to_string().contains("already used by a"));
+    }
+
+    #[test]
+    fn restarted_instance_can_not_take_addresses_of_others() {
+        let status_addr = SocketAddr::from(([0, 0, 0, 0], 10100));
+        let instances: Vec<Instance> = [("a", 10000), ("b", 10010)]
+            .into_iter()
+            .map(|(name, port)| Instance {
+                name: name.to_string(),
+                dir: PathBuf::from(name),
+                status: InstanceStatus {
+                    name: name.to_string(),
+                    state: InstanceState::Restarting,
+                    restarts: 1,
+                    last_error: None,
+                    advertise_addr: String::new(),
+                    listen_addr: String::new(),
+                    api_addr: String::new(),
+                    live_nodes: 0,
+                },
+                config: config(port),
+                chitchat: None,
+            })
+            .collect();
+
+        // The own addresses are free, the new ones are checked against the running instances
+        assert!(check_restart_addresses(&instances, 1, &config(10010), status_addr).is_ok());
+        assert!(check_restart_addresses(&instances, 1, &config(10020), status_addr).is_ok());
+        let mut changed = config(10020);
+        changed.api_addr = config(10000).api_addr;
+        let err = check_restart_addresses(&instances, 1, &changed, status_addr).unwrap_err();
+        assert_eq!(err.to_string(), "Instance b: api_addr port 10001 is already used by a");
+    }
+}
@@ -1,60 +1,134 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::vec;
 
+use poem_openapi::Enum;
+use poem_openapi::Object;
 use serde::Deserialize;
 use serde::Serialize;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+pub fn init_tracing() {
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer() //
+                .with_file(true)
+                .with_line_number(true),
+        )
+        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+}
 
 pub trait ContainsVec<T: HasTimestampAndId> {
     fn get_mut_vec(&mut self) -> &mut Vec<T>;
     fn is_empty(&self) -> bool;
     fn get_pk(&self) -> String;
+    // Chitchat generation of the node state, tells apart the states of a restarted node
+    fn get_generation(&self) -> u64;
 }
 
 pub trait HasTimestampAndId {
     fn get_id(&self) -> String;
     fn get_timestamp(&self) -> u64;
+    fn get_provider(&self) -> String;
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Object)]
 pub struct RevokedLicense {
     pub license_id: String,
     pub provider_pubkey: String,
     pub timestamp: u64,
+    #[serde(default)]
+    #[oai(default)]
+    pub reason: RevocationReason,
+    // Reason given in the backend revocation list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, Enum, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum RevocationReason {
+    // The license has been delegated to another node with a newer timestamp
+    #[default]
+    ReDelegated,
+    // The license is in the signed revocation list of the backend, see `revocations`
+    RevokedByBackend,
+}
+
+// A license delegated to several nodes with the same latest timestamp
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Object)]
+pub struct LicenseConflict {
+    pub license_id: String,
+    pub timestamp: u64,
+    // The delegation that is kept
+    pub kept_provider_pubkey: String,
+    pub kept_node_pubkey: String,
+    // The delegation that is dropped
+    pub dropped_provider_pubkey: String,
+    pub dropped_node_pubkey: String,
 }
 
-pub fn remove_with_outdated_timestamps<S, T>(mut data: Vec<S>) -> (Vec<S>, Vec<RevokedLicense>)
+// Keeps every license on a single node: the one with the latest delegation timestamp. Equal
+// timestamps are resolved by the lowest provider pubkey, then the lowest node pubkey and then the
+// newest chitchat generation, the dropped delegations are returned as conflicts.
+pub fn remove_with_outdated_timestamps<S, T>(
+    mut data: Vec<S>,
+) -> (Vec<S>, Vec<RevokedLicense>, Vec<LicenseConflict>)
 where
     T: HasTimestampAndId + std::fmt::Debug,
     S: ContainsVec<T>,
 {
-    let mut max_map: HashMap<String, u64> = HashMap::new();
+    // The smallest key wins
+    let mut winners: HashMap<String, (Reverse<u64>, String, String, Reverse<u64>)> = HashMap::new();
     for inner in &mut data {
+        let pk = inner.get_pk();
+        let generation = Reverse(inner.get_generation());
         for item in inner.get_mut_vec() {
-            max_map
+            let key = (Reverse(item.get_timestamp()), item.get_provider(), pk.clone(), generation);
+            winners
                 .entry(item.get_id())
-                .and_modify(|ts| {
-                    if item.get_timestamp() > *ts {
-                        *ts = item.get_timestamp();
+                .and_modify(|winner| {
+                    if key < *winner {
+                        *winner = key.clone();
                     }
                 })
-                .or_insert(item.get_timestamp());
+                .or_insert(key);
         }
     }
 
     let mut revoked_licenses = vec![];
+    let mut conflicts = vec![];
     for inner in &mut data {
         let pk = inner.get_pk();
+        let generation = inner.get_generation();
         inner.get_mut_vec().retain(|item| {
-            max_map.get(&item.get_id()).is_some_and(|&max_ts| {
-                if item.get_timestamp() == max_ts {
+            winners.get(&item.get_id()).is_some_and(|(Reverse(max_ts), provider, node, gen)| {
+                if item.get_timestamp() < *max_ts {
+                    revoked_licenses.push(RevokedLicense {
+                        license_id: item.get_id(),
+                        provider_pubkey: pk.clone(),
+                        timestamp: item.get_timestamp(),
+                        reason: RevocationReason::ReDelegated,
+                        details: None,
+                    });
+                    return false; // remove the item if its timestamp is outdated
+                }
+                if item.get_provider() == *provider && pk == *node && generation == gen.0 {
                     return true;
                 }
-                revoked_licenses.push(RevokedLicense {
+                conflicts.push(LicenseConflict {
                     license_id: item.get_id(),
-                    provider_pubkey: pk.clone(),
-                    timestamp: item.get_timestamp(),
+                    timestamp: *max_ts,
+                    kept_provider_pubkey: provider.clone(),
+                    kept_node_pubkey: node.clone(),
+                    dropped_provider_pubkey: item.get_provider(),
+                    dropped_node_pubkey: pk.clone(),
                 });
-                false // remove the item if its timestamp is outdated
+                false
             })
         });
     }
@@ -75,7 +149,7 @@ where
     // Remove empty inner vectors
     data.retain(|inner| !inner.is_empty());
 
-    (data, revoked_licenses)
+    (data, revoked_licenses, conflicts)
 }
 
 #[cfg(test)]
@@ -86,6 +160,7 @@ mod tests {
     struct InnerStruct {
         id: String,
         ts: u64,
+        provider: &'static str,
     }
     impl HasTimestampAndId for InnerStruct {
         fn get_id(&self) -> String {
@@ -95,11 +170,16 @@ mod tests {
         fn get_timestamp(&self) -> u64 {
             self.ts
         }
+
+        fn get_provider(&self) -> String {
+            self.provider.to_string()
+        }
     }
 
     #[derive(Debug)]
     struct OuterStruct {
         index: usize,
+        generation: u64,
         inner: Vec<InnerStruct>,
     }
     impl ContainsVec<InnerStruct> for OuterStruct {
@@ -114,6 +194,10 @@ mod tests {
         fn get_pk(&self) -> String {
             format!("pk_{}", self.index)
         }
+
+        fn get_generation(&self) -> u64 {
+            self.generation
+        }
     }
 
     #[test]
@@ -121,27 +205,30 @@ mod tests {
         let data: Vec<OuterStruct> = vec![];
         let expected_output: Vec<OuterStruct> = vec![];
         let expected_problem: Vec<String> = vec![];
-        let (output, problem) = remove_with_outdated_timestamps(data);
+        let (output, problem, conflicts) = remove_with_outdated_timestamps(data);
         assert_eq!(format!("{output:?}"), format!("{expected_output:?}"));
         assert_eq!(format!("{problem:?}"), format!("{expected_problem:?}"));
+        assert!(conflicts.is_empty());
     }
 
     #[test]
     fn test_remove_with_outdated_timestamps_2() {
         let data: Vec<OuterStruct> = vec![OuterStruct {
             index: 0,
+            generation: 0,
             inner: vec![
-                InnerStruct { id: "a".into(), ts: 10 }, // retained
-                InnerStruct { id: "b".into(), ts: 20 }, // removed
-                InnerStruct { id: "b".into(), ts: 40 }, // retained
-                InnerStruct { id: "a".into(), ts: 10 }, // removed as duplicate
+                InnerStruct { id: "a".into(), ts: 10, provider: "p" }, // retained
+                InnerStruct { id: "b".into(), ts: 20, provider: "p" }, // removed
+                InnerStruct { id: "b".into(), ts: 40, provider: "p" }, // retained
+                InnerStruct { id: "a".into(), ts: 10, provider: "p" }, // removed as duplicate
             ],
         }];
         let expected_output: Vec<OuterStruct> = vec![OuterStruct {
             index: 0,
+            generation: 0,
             inner: vec![
-                InnerStruct { id: "a".into(), ts: 10 },
-                InnerStruct { id: "b".into(), ts: 40 },
+                InnerStruct { id: "a".into(), ts: 10, provider: "p" },
+                InnerStruct { id: "b".into(), ts: 40, provider: "p" },
             ],
         }];
 
@@ -149,45 +236,56 @@ mod tests {
             provider_pubkey: "pk_0".to_string(),
             license_id: "b".to_string(),
             timestamp: 20,
+            reason: RevocationReason::ReDelegated,
+            details: None,
         }];
-        let (output, problem) = remove_with_outdated_timestamps(data);
+        let (output, problem, conflicts) = remove_with_outdated_timestamps(data);
 
         assert_eq!(format!("{output:?}"), format!("{expected_output:?}"));
         assert_eq!(format!("{problem:?}"), format!("{expected_problem:?}"));
+        assert!(conflicts.is_empty());
     }
     #[test]
     fn test_remove_with_outdated_timestamps_3() {
         let data = vec![
             OuterStruct {
                 index: 0,
+                generation: 0,
                 inner: vec![
-                    InnerStruct { id: "a".into(), ts: 10 },
-                    InnerStruct { id: "b".into(), ts: 20 },
+                    InnerStruct { id: "a".into(), ts: 10, provider: "p" },
+                    InnerStruct { id: "b".into(), ts: 20, provider: "p" },
                 ],
             },
             OuterStruct {
                 index: 1,
+                generation: 0,
                 inner: vec![
-                    InnerStruct { id: "a".into(), ts: 15 },
-                    InnerStruct { id: "c".into(), ts: 30 },
+                    InnerStruct { id: "a".into(), ts: 15, provider: "p" },
+                    InnerStruct { id: "c".into(), ts: 30, provider: "p" },
                 ],
             },
-            OuterStruct { index: 2, inner: vec![InnerStruct { id: "b".into(), ts: 5 }] },
+            OuterStruct {
+                index: 2,
+                generation: 0,
+                inner: vec![InnerStruct { id: "b".into(), ts: 5, provider: "p" }],
+            },
         ];
 
         let expected_output = vec![
             OuterStruct {
                 index: 0,
+                generation: 0,
                 inner: vec![
                     //
-                    InnerStruct { id: "b".into(), ts: 20 },
+                    InnerStruct { id: "b".into(), ts: 20, provider: "p" },
                 ],
             },
             OuterStruct {
                 index: 1,
+                generation: 0,
                 inner: vec![
-                    InnerStruct { id: "a".into(), ts: 15 },
-                    InnerStruct { id: "c".into(), ts: 30 },
+                    InnerStruct { id: "a".into(), ts: 15, provider: "p" },
+                    InnerStruct { id: "c".into(), ts: 30, provider: "p" },
                 ],
             },
         ];
@@ -197,17 +295,77 @@ mod tests {
                 license_id: "a".to_string(),
                 provider_pubkey: "pk_0".to_string(),
                 timestamp: 10,
+                reason: RevocationReason::ReDelegated,
+                details: None,
             },
             RevokedLicense {
                 license_id: "b".to_string(),
                 provider_pubkey: "pk_2".to_string(),
                 timestamp: 5,
+                reason: RevocationReason::ReDelegated,
+                details: None,
             },
         ];
 
-        let (output, problem) = remove_with_outdated_timestamps(data);
+        let (output, problem, conflicts) = remove_with_outdated_timestamps(data);
 
         assert_eq!(format!("{output:?}"), format!("{expected_output:?}"));
         assert_eq!(format!("{problem:?}"), format!("{expected_problem:?}"));
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn equal_timestamps_are_resolved_by_provider_then_node() {
+        let data = vec![
+            OuterStruct {
+                index: 0,
+                generation: 0,
+                inner: vec![InnerStruct { id: "a".into(), ts: 10, provider: "p2" }],
+            },
+            OuterStruct {
+                index: 1,
+                generation: 0,
+                inner: vec![InnerStruct { id: "a".into(), ts: 10, provider: "p1" }],
+            },
+            OuterStruct {
+                index: 2,
+                generation: 0,
+                inner: vec![InnerStruct { id: "a".into(), ts: 10, provider: "p1" }],
+            },
+            OuterStruct {
+                index: 3,
+                generation: 0,
+                inner: vec![InnerStruct { id: "a".into(), ts: 5, provider: "p0" }],
+            },
+        ];
+
+        let (output, problem, conflicts) = remove_with_outdated_timestamps(data);
+
+        let kept: Vec<usize> = output.iter().map(|outer| outer.index).collect();
+        assert_eq!(kept, [1]);
+        assert_eq!(problem.len(), 1);
+        assert_eq!(problem[0].provider_pubkey, "pk_3");
+        let dropped: Vec<(&str, &str)> = conflicts
+            .iter()
+            .map(|c| (c.dropped_provider_pubkey.as_str(), c.dropped_node_pubkey.as_str()))
+            .collect();
+        assert_eq!(dropped, [("p2", "pk_0"), ("p1", "pk_2")]);
+        assert!(conflicts.iter().all(|c| c.kept_node_pubkey == "pk_1" && c.timestamp == 10));
+    }
+
+    #[test]
+    fn states_of_a_restarted_node_keep_a_license_once() {
+        let state = |generation| OuterStruct {
+            index: 0,
+            generation,
+            inner: vec![InnerStruct { id: "a".into(), ts: 10, provider: "p" }],
+        };
+        let (output, problem, conflicts) =
+            remove_with_outdated_timestamps(vec![state(1), state(2)]);
+
+        let kept: Vec<u64> = output.iter().map(|outer| outer.generation).collect();
+        assert_eq!(kept, [2]);
+        assert!(problem.is_empty());
+        assert_eq!(conflicts.len(), 1);
     }
 }
@@ -0,0 +1,291 @@
+// Verified view of the cluster shared by the API and the background tasks. Signature checks
+// are the expensive part of the verification, so the result for every node is kept under its
+// (ChitchatId, max_version) and reused until the node state changes. The view is rebuilt on
+// chitchat membership and state changes, when a new revocation list becomes active, and every
+// REFRESH_INTERVAL for the dead nodes the watcher does not report.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chitchat::ChitchatId;
+use chitchat::ChitchatRef;
+use chitchat::NodeState;
+use rayon::prelude::*;
+use tokio::sync::watch;
+
+use crate::backend_keys::BackendKeyring;
+use crate::config::TimestampPolicy;
+use crate::open_api::routes::validate_node;
+use crate::open_api::routes::validate_parsed_node;
+use crate::open_api::routes::NodeRejection;
+use crate::open_api::routes::ValidationStep;
+use crate::open_api::routes::VerifiedNodeState;
+use crate::open_api::routes::VerifiedNodeStateNoLicenses;
+use crate::revocations::RevocationList;
+use crate::utils::LicenseConflict;
+use crate::utils::RevokedLicense;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+type Versioned<T> = HashMap<ChitchatId, (u64, T)>;
+
+#[derive(Debug, Default)]
+pub struct VerifiedView {
+    pub live_nodes: BTreeMap<ChitchatId, NodeState>,
+    pub verified_state: Vec<VerifiedNodeState>,
+    pub revoked_licenses: Vec<RevokedLicense>,
+    pub conflicts: Vec<LicenseConflict>,
+    // License id to the id of the backend key that verified its proof, shown in `/state` and
+    // `/nodes`
+    pub backend_key_ids: BTreeMap<String, String>,
+    // Revocation list the view was built with
+    pub revocations: Arc<RevocationList>,
+    pub(crate) timestamp_policy: TimestampPolicy,
+    pub(crate) backend_keys: Arc<BackendKeyring>,
+    // `validate_node` result of every node in the snapshot the view was built from
+    pub(crate) validations: Versioned<Result<(), NodeRejection>>,
+}
+
+impl VerifiedView {
+    // The cached result if the node has not changed since the view was built
+    pub fn validate(&self, node_state: &NodeState) -> Result<(), NodeRejection> {
+        match self.validations.get(node_state.chitchat_id()) {
+            Some((version, result)) if *version == node_state.max_version() => result.clone(),
+            _ => validate_node(
+                &key_values(node_state),
+                &self.verified_state,
+                &self.timestamp_policy,
+                &self.backend_keys,
+            ),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VerificationCache {
+    receiver: watch::Receiver<Arc<VerifiedView>>,
+}
+
+impl VerificationCache {
+    // The first view is built before it returns, the task stops when the last clone is dropped
+    pub async fn spawn(
+        chitchat: ChitchatRef,
+        timestamp_policy: TimestampPolicy,
+        backend_keys: Arc<BackendKeyring>,
+        mut revocations: watch::Receiver<Arc<RevocationList>>,
+    ) -> Self {
+        let mut watcher = chitchat.lock().live_nodes_watcher();
+        let mut builder =
+            ViewBuilder { chitchat, parsed: Versioned::default(), timestamp_policy, backend_keys };
+        let live_nodes = watcher.borrow_and_update().clone();
+        let revocation_list = revocations.borrow_and_update().clone();
+        let view = builder.build(live_nodes, revocation_list).await;
+        let (sender, receiver) = watch::channel(Arc::new(view));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                tokio::select! {
+                    changed = watcher.changed() => if changed.is_err() {
+                        return;
+                    },
+                    // A closed channel disables this branch, the last list stays in use
+                    Ok(()) = revocations.changed() => {}
+                    _ = interval.tick() => {}
+                    _ = sender.closed() => return,
+                }
+                let live_nodes = watcher.borrow_and_update().clone();
+                let revocation_list = revocations.borrow_and_update().clone();
+                let view = builder.build(live_nodes, revocation_list).await;
+                // Don't wake up the subscribers if nothing has changed
+                sender.send_if_modified(|current| {
+                    let modified = current.validations != view.validations
+                        || current.live_nodes.keys().ne(view.live_nodes.keys())
+                        || current.revocations.version != view.revocations.version;
+                    if modified {
+                        *current = Arc::new(view);
+                    }
+                    modified
+                });
+            }
+        });
+        Self { receiver }
+    }
+
+    pub fn get(&self) -> Arc<VerifiedView> {
+        self.receiver.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<VerifiedView>> {
+        self.receiver.clone()
+    }
+}
+
+struct ViewBuilder {
+    chitchat: ChitchatRef,
+    parsed: Versioned<Result<VerifiedNodeStateNoLicenses, NodeRejection>>,
+    timestamp_policy: TimestampPolicy,
+    backend_keys: Arc<BackendKeyring>,
+}
+
+impl ViewBuilder {
+    // The signatures are checked on the rayon pool, a blocking thread waits for them instead
+    // of a runtime worker
+    async fn build(
+        &mut self,
+        live_nodes: BTreeMap<ChitchatId, NodeState>,
+        revocations: Arc<RevocationList>,
+    ) -> VerifiedView {
+        let chitchat = self.chitchat.clone();
+        let mut parsed = std::mem::take(&mut self.parsed);
+        let timestamp_policy = self.timestamp_policy;
+        let backend_keys = self.backend_keys.clone();
+        let (view, parsed) = tokio::task::spawn_blocking(move || {
+            let view = build_view(
+                &chitchat,
+                live_nodes,
+                &mut parsed,
+                &timestamp_policy,
+                &backend_keys,
+                revocations,
+            );
+            (view, parsed)
+        })
+        .await
+        .expect("Verified view build panicked");
+        self.parsed = parsed;
+        view
+    }
+}
+
+fn build_view(
+    chitchat: &ChitchatRef,
+    live_nodes: BTreeMap<ChitchatId, NodeState>,
+    parsed: &mut Versioned<Result<VerifiedNodeStateNoLicenses, NodeRejection>>,
+    timestamp_policy: &TimestampPolicy,
+    backend_keys: &Arc<BackendKeyring>,
+    revocations: Arc<RevocationList>,
+) -> VerifiedView {
+    let node_states = chitchat.lock().state_snapshot().node_states;
+
+    // Entries of the removed nodes and the outdated versions are dropped here
+    let mut previous = std::mem::take(parsed);
+    let mut changed = vec![];
+    for node_state in &node_states {
+        let id = node_state.chitchat_id();
+        let version = node_state.max_version();
+        match previous.remove(id) {
+            // A timestamp in the future becomes valid with time, such nodes are checked again
+            Some((cached_version, Err(rejection)))
+                if cached_version == version && rejection.step == ValidationStep::Timestamps =>
+            {
+                changed.push((node_state, Some(rejection)));
+            }
+            Some((cached_version, result)) if cached_version == version => {
+                parsed.insert(id.clone(), (version, result));
+            }
+            _ => changed.push((node_state, None)),
+        }
+    }
+    // After a restart every node is verified here, the nodes are spread across the cores
+    let verified: Vec<_> = changed
+        .par_iter()
+        .map(|(node_state, _)| {
+            VerifiedNodeStateNoLicenses::from_gossip(
+                key_values(node_state),
+                timestamp_policy,
+                backend_keys,
+            )
+            .map(|node| node.with_generation(node_state.chitchat_id().generation_id))
+        })
+        .collect();
+    for ((node_state, previous), result) in changed.into_iter().zip(verified) {
+        match &result {
+            Err(err) if previous.as_ref() != Some(err) => {
+                tracing::error!("Skip invalid data of {:?}: {err}", node_state.chitchat_id());
+            }
+            _ => {}
+        }
+        parsed.insert(node_state.chitchat_id().clone(), (node_state.max_version(), result));
+    }
+
+    // `/export` lists the nodes in the snapshot order
+    let nodes: Vec<VerifiedNodeStateNoLicenses> = node_states
+        .iter()
+        .filter_map(|node_state| parsed[node_state.chitchat_id()].1.as_ref().ok().cloned())
+        .collect();
+    let (verified_state, revoked_licenses, conflicts) =
+        VerifiedNodeState::from_state(nodes, &revocations);
+    let backend_key_ids = VerifiedNodeState::backend_key_ids(
+        parsed.values().filter_map(|(_, result)| result.as_ref().ok()),
+        &verified_state,
+    );
+
+    let validations = node_states
+        .iter()
+        .map(|node_state| {
+            let (version, result) = &parsed[node_state.chitchat_id()];
+            let validation = result.as_ref().map_err(Clone::clone).and_then(|node| {
+                validate_parsed_node(node, &key_values(node_state), &verified_state)
+            });
+            (node_state.chitchat_id().clone(), (*version, validation))
+        })
+        .collect();
+    VerifiedView {
+        live_nodes,
+        verified_state,
+        revoked_licenses,
+        conflicts,
+        backend_key_ids,
+        revocations,
+        validations,
+        timestamp_policy: *timestamp_policy,
+        backend_keys: backend_keys.clone(),
+    }
+}
+
+fn key_values(node_state: &NodeState) -> HashMap<String, String> {
+    node_state.key_values().map(|(k, v)| (k.into(), v.into())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::test_chitchat;
+    use crate::network::NetworkProfile;
+    use crate::ZerostateKeys;
+
+    #[tokio::test]
+    async fn verification_is_reused_until_the_node_changes() {
+        let key_values = vec![(ZerostateKeys::Pubkey.to_string(), "a".to_string())];
+        let handle = test_chitchat("test", 10000, key_values).await;
+        let chitchat = handle.chitchat();
+        let chitchat_id = chitchat.lock().self_chitchat_id().clone();
+
+        let policy = TimestampPolicy::default();
+        let keys = Arc::new(BackendKeyring::from_config(&NetworkProfile::devnet(), &[]).unwrap());
+        let mut parsed = Versioned::default();
+        let view =
+            build_view(&chitchat, BTreeMap::new(), &mut parsed, &policy, &keys, Arc::default());
+        let (version, result) = &view.validations[&chitchat_id];
+        assert_eq!(result.as_ref().unwrap_err().step, ValidationStep::Fields);
+
+        // The node has not changed, the cached result is used as is
+        let cached = NodeRejection { step: ValidationStep::Signatures, reason: "cached".into() };
+        parsed.insert(chitchat_id.clone(), (*version, Err(cached.clone())));
+        let view =
+            build_view(&chitchat, BTreeMap::new(), &mut parsed, &policy, &keys, Arc::default());
+        assert_eq!(view.validations[&chitchat_id].1, Err(cached));
+
+        chitchat.lock().self_node_state().set(ZerostateKeys::Version.to_string(), "0.1.0");
+        let own_state = chitchat.lock().self_node_state().clone();
+        // The view is older than the node state, the node is verified again
+        assert_eq!(view.validate(&own_state).unwrap_err().step, ValidationStep::Fields);
+        let view =
+            build_view(&chitchat, BTreeMap::new(), &mut parsed, &policy, &keys, Arc::default());
+        let (new_version, result) = &view.validations[&chitchat_id];
+        assert!(new_version > version);
+        assert_eq!(result.as_ref().unwrap_err().step, ValidationStep::Fields);
+    }
+}